@@ -0,0 +1,88 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tm::cron::expression::Expression;
+
+// Medians of `cargo bench -- --warm-up-time 1 --measurement-time 2` on one machine.
+// Before is the evaluator that rebuilt a schedule for every month it searched,
+// after is the bitmask evaluator, both measured with this file
+// benchmark                  before      after
+// parse/every_minute      9.1883 µs  3.7004 µs
+// parse/weekdays          9.8486 µs  3.7218 µs
+// parse/steps             11.282 µs  4.3324 µs
+// parse/last_day          10.526 µs  3.6980 µs
+// parse/nth_dow           7.9917 µs  3.5586 µs
+// parse/leap_day          13.033 µs  4.6395 µs
+// next/every_minute       11.549 µs  166.83 ns
+// next/weekdays           8.0612 µs  165.05 ns
+// next/steps              7.9070 µs  170.23 ns
+// next/last_day           8.7234 µs  184.50 ns
+// next/nth_dow            8.6549 µs  206.67 ns
+// next/leap_day           18.249 µs  544.59 ns
+// prev/every_minute       7.5837 µs  145.11 ns
+// prev/weekdays           15.095 µs  317.70 ns
+// prev/steps              8.1822 µs  145.52 ns
+// prev/last_day           12.525 µs  260.06 ns
+// prev/nth_dow            13.919 µs  635.74 ns
+// prev/leap_day           6.0765 µs  138.24 ns
+// matches/every_minute    4.4370 µs  11.530 ns
+// matches/weekdays        2.6920 µs  9.5404 ns
+// matches/steps           2.5586 µs  9.4721 ns
+// matches/last_day        2.0059 µs  8.7347 ns
+// matches/nth_dow         2.5154 µs  8.4664 ns
+// matches/leap_day        2.2108 µs  10.811 ns
+
+const EXPRESSIONS: [(&str, &str); 6] = [
+    ("every_minute", "* * * * *"),
+    ("weekdays", "0 22 * * 1-5"),
+    ("steps", "*/7 3 1-10/3 */2 *"),
+    ("last_day", "15 10 L * *"),
+    ("nth_dow", "0 0 * * 6#5"),
+    ("leap_day", "0 0 29 2 *"),
+];
+
+fn start() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, 3, 1)
+        .unwrap()
+        .and_hms_opt(12, 34, 56)
+        .unwrap()
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, expression) in EXPRESSIONS {
+        group.bench_function(name, |b| {
+            b.iter(|| Expression::from_str(black_box(expression)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn next(c: &mut Criterion) {
+    let mut group = c.benchmark_group("next");
+    for (name, expression) in EXPRESSIONS {
+        let expression = Expression::from_str(expression).unwrap();
+        group.bench_function(name, |b| b.iter(|| expression.next(black_box(start()))));
+    }
+    group.finish();
+}
+
+fn prev(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prev");
+    for (name, expression) in EXPRESSIONS {
+        let expression = Expression::from_str(expression).unwrap();
+        group.bench_function(name, |b| b.iter(|| expression.prev(black_box(start()))));
+    }
+    group.finish();
+}
+
+fn matches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("matches");
+    for (name, expression) in EXPRESSIONS {
+        let expression = Expression::from_str(expression).unwrap();
+        group.bench_function(name, |b| b.iter(|| expression.matches(black_box(start()))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, next, prev, matches);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+# Graphs can run on timetables other than cron expressions
+
+from tm import task, Graph, Executor
+from tm.cron import Interval, AfterRun
+from datetime import datetime, timedelta
+from time import sleep
+
+@task()
+def report():
+    print("Report started at", datetime.now())
+    sleep(3)
+
+# Every 90 minutes, counting from a start time instead of from now
+interval = Graph(name="interval", schedule=Interval("1h30m", start=datetime(2024,1,1,0,15)))
+interval.add_edges([report])
+
+# 10 seconds after the previous run finishes, runs never overlap
+# The first run starts right away
+after_run = Graph(name="after run", schedule=AfterRun(timedelta(seconds=10)))
+after_run.add_edges([report])
+
+# Any object with a next(after) method, `after` is an aware UTC datetime.
+# Return the next run as a datetime (naive means UTC) or None to stop
+class Backoff:
+    def __init__(self):
+        self.delay = timedelta(seconds=1)
+
+    def next(self, after):
+        self.delay *= 2
+        if self.delay > timedelta(minutes=1):
+            return None
+        return after + self.delay
+
+backoff = Graph(name="backoff", schedule=Backoff())
+backoff.add_edges([report])
+
+Executor(graphs=[interval, after_run, backoff]).start()
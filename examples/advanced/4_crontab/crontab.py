@@ -0,0 +1,15 @@
+# Existing crontabs can be scheduled without rewriting them as graphs
+
+import os
+import tm
+from tm import Executor
+
+# One graph per entry, named <file name>:<line number>, with a single task
+# that runs the command. Pass user_column=True for system crontabs like /etc/crontab
+path = os.path.join(os.path.dirname(__file__), "crontab")
+graphs = tm.from_crontab(path)
+
+for graph in graphs:
+    print(graph.name(), graph.next())
+
+Executor(graphs).start()
@@ -1,7 +1,7 @@
 # Using only the cron engine
 
-from tm.cron import Expression
-from datetime import datetime
+from tm.cron import Expression, CronParseError, Schedule, Calendar
+from datetime import date, datetime, timezone
 
 # supports:
 
@@ -27,6 +27,21 @@ from datetime import datetime
 # --- (40,0-30/15 * * * *)
 # --- (* */12 2 jan,jun thu-sat)
 
+# - Quartz-style day modifiers
+# --- (0 0 L * *) last day of the month, (0 0 L-3 * *) three days before it
+# --- (0 0 LW * *) last weekday of the month
+# --- (0 0 15W * *) weekday nearest to the 15th
+# --- (0 0 * * 5L) last Friday of the month
+# --- (0 0 * * 2#3) third Tuesday of the month
+
+# - Macros
+# --- @yearly (@annually), @monthly, @weekly, @daily (@midnight), @hourly
+# --- (@every 1h30m) fixed delay between runs, supports h, m and s units
+
+# - An optional leading seconds field (6 fields)
+# --- (*/15 * * * * *) every fifteen seconds
+# --- (30 0 * * * *) at second 30 of every hour
+
 
 # minutes 1,2 and every 15 minutes (0, 15, 30, 45)
 # at hour 00
@@ -35,6 +50,9 @@ from datetime import datetime
 expr = Expression("1-2,*/15 0 1 3 2")
 t = datetime(2024,1,31,20)
 
+print(expr) # 1-2,*/15 0 1 3 2
+print(expr.describe()) # At minutes 1, 2 and every 15 minutes past hour 0, on day 1 and on Tuesdays in March
+
 t = expr.next(t)
 print(t) # 2024-03-01 00:00:00
 t = expr.next(t)
@@ -43,3 +61,70 @@ t = expr.next(t)
 print(t) # 2024-03-01 00:02:00
 t = expr.next(t)
 print(t) # 2024-03-01 00:15:00
+
+# Or lazily iterate over the upcoming fire times
+for t in expr.iter_from(datetime(2024,1,31,20)).take(4):
+    print(t)
+
+# Fire times from start (inclusive) up to end (exclusive)
+runs = expr.between(datetime(2024,3,1), datetime(2024,3,2))
+print(len(list(runs))) # 6
+print(expr.count_between(datetime(2024,1,1), datetime(2025,1,1))) # 30
+
+# Check whether a datetime is on the schedule
+print(expr.matches(datetime(2024,3,5,0,15))) # True
+print(expr.matches(datetime(2024,3,5,0,16))) # False
+
+# Go back in time to find when the schedule last fired
+t = expr.prev(t)
+print(t) # 2024-03-01 00:02:00
+
+# Expressions are evaluated in UTC unless given an IANA timezone
+# Wall-clock times skipped by DST fire after the gap (02:30 -> 03:30),
+# and times repeated by DST fire once, on their first occurrence
+expr = Expression("30 2 * * *", timezone="Europe/Berlin")
+
+# naive datetimes are wall-clock times in the expression's timezone
+print(expr.next(datetime(2024,3,30,3))) # 2024-03-31 03:30:00
+
+# aware datetimes are converted and returned in the expression's timezone
+print(expr.next(datetime(2024,3,30,2,tzinfo=timezone.utc))) # 2024-03-31 03:30:00+02:00
+
+# Schedules with a year field or a one-shot `at:` run out,
+# next returns None after the last fire time
+expr = Expression("at:2026-12-31T23:00")
+print(expr.next(datetime(2026,1,1))) # 2026-12-31 23:00:00
+print(expr.next(datetime(2027,1,1))) # None
+
+# Restricted day-of-month and day-of-week fields match either one by default,
+# "and" requires both, e.g. the first Monday of the month
+expr = Expression("0 9 1-7 * mon", day_semantics="and")
+print(expr.next(datetime(2024,3,5))) # 2024-04-01 09:00:00
+
+# Expressions combine with | (union), & (intersection) and - (exclusion),
+# strings are parsed as expressions. Each side keeps its own timezone,
+# naive datetimes are treated as UTC
+sched = (Expression("0 9 * * mon-fri") | "0 12 * * sat") - "* * 25 12 *"
+print(sched) # (Expression("0 9 * * 1-5") | Expression("0 12 * * 6")) - Expression("* * 25 12 *")
+print(sched.next(datetime(2026,12,24,10))) # 2026-12-26 12:00:00
+print(Schedule(["0 9 * * *", "0 21 * * *"]).next(datetime(2024,1,1,10))) # 2024-01-01 21:00:00
+
+# H picks a stable value from a hash of hash_key, Graphs use their names,
+# so graphs sharing a schedule don't all start at once
+print(Expression("H * * * *", hash_key="reports")) # 9 * * * *
+print(Expression("H * * * *", hash_key="backups")) # 8 * * * *
+
+# Calendars exclude weekends and holidays, given as dates or loaded with
+# Calendar.from_file from an .ics file or a .json list of "YYYY-MM-DD" dates.
+# Fire times on other days are skipped, or moved to the next business day
+calendar = Calendar([date(2024,12,25), date(2024,12,26)], on_holiday="shift")
+expr = Expression("0 9 25 12 *", calendar=calendar)
+print(expr.next(datetime(2024,12,1))) # 2024-12-27 09:00:00
+
+# Invalid expressions raise CronParseError, a ValueError with the field,
+# comma-separated part and character offset of the bad token
+try:
+    Expression("0 0 * 1-13 *")
+except CronParseError as e:
+    print(e) # Value 13 for field month must be between 1 and 12 (part 0, offset 8)
+    print(e.field, e.part, e.offset) # month 0 8
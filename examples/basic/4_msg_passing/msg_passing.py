@@ -50,7 +50,9 @@ def leaf(add_2, add_3, do_nothing):
     print("Leaf received all inputs correctly")
 
 # create the graph
-graph = Graph(name="message passing demo", schedule="* * * * *")
+# add_2, add_3 and do_nothing only depend on root, so they run in parallel.
+# max_parallel_tasks caps how many tasks of the graph run at once (no limit by default)
+graph = Graph(name="message passing demo", schedule="* * * * *", max_parallel_tasks=3)
 
 # Define the nodes of the graph
 
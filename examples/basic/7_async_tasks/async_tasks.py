@@ -0,0 +1,29 @@
+# Async tasks
+
+import asyncio
+from tm import task, Graph, Executor
+
+# async def tasks are awaited, children get the result and not the coroutine
+@task()
+async def fetch_users():
+    await asyncio.sleep(1) # e.g. an HTTP request
+    return ["alice", "bob"]
+
+@task()
+async def fetch_orders():
+    await asyncio.sleep(1)
+    return {"alice": 2, "bob": 0}
+
+# retries work like for any task, each try awaits the function again
+@task(retries = 2, retry_delay = 1)
+async def report(fetch_users, fetch_orders):
+    for user in fetch_users:
+        print(f"{user} has {fetch_orders[user]} orders")
+
+# fetch_users and fetch_orders don't depend on each other,
+# so they run concurrently and the graph takes about a second
+graph = Graph(name="async demo", schedule="* * * * *")
+
+graph.add_edges([fetch_users, fetch_orders], [report])
+
+Executor(graphs=[graph]).start()
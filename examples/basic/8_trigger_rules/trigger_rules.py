@@ -0,0 +1,47 @@
+# Trigger rules, running tasks after upstream failures
+
+from tm import task, Graph, Executor
+
+#            extract
+#               |
+#           transform (fails)
+#            /      \
+#         load     alert (one_failed)
+#            \      /
+#            cleanup (all_done)
+
+@task()
+def extract():
+    return [1, 2, "three"]
+
+@task()
+def transform(extract):
+    return [int(row) for row in extract]  # fails on "three"
+
+# the default rule, all_success, runs a task if all its parents succeeded.
+# load is marked upstream_failed instead, and its children get None from it
+@task()
+def load(transform):
+    print(f"loading {transform}")
+
+# one_failed runs a task if any of its parents failed
+@task(trigger_rule = "one_failed")
+def alert(transform):
+    print("transform failed, sending an alert")
+
+# all_done runs a task once its parents are done, whatever happened to them.
+# none_failed is also available, it runs unless a parent failed, skipped parents are fine
+@task(trigger_rule = "all_done")
+def cleanup(load, alert):
+    print("cleaning up")
+
+graph = Graph(name="trigger rules demo", schedule="* * * * *")
+
+graph.add_edges([extract], [transform])
+graph.add_edges([transform], [load, alert])
+graph.add_edges([load, alert], [cleanup])
+
+# The run is logged as failed since transform failed, even if alert handled it.
+# After a run, graph.states() has the state of every task:
+# success, failed, skipped or upstream_failed
+Executor(graphs=[graph]).start()
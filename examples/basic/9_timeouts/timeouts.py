@@ -0,0 +1,30 @@
+# Task and graph timeouts
+
+import time
+from tm import task, Graph, Executor
+
+calls = 0
+
+# An attempt that takes longer than timeout (in seconds) fails like any other
+# and is retried. async def tasks are cancelled, but Python can't stop a running
+# function so the hung call keeps running in the background until the run ends
+@task(timeout = 2, retries = 1, retry_delay = 1)
+def flaky_request():
+    global calls
+    calls += 1
+    if calls == 1:
+        time.sleep(60) # e.g. an HTTP call that never returns
+    return "response"
+
+@task()
+def hangs(flaky_request):
+    print(f"got {flaky_request}")
+    time.sleep(60)
+
+# The graph timeout stops the whole run, tasks still running are left behind
+# and the run is logged with a timed_out status instead of failed
+graph = Graph(name="timeout demo", schedule="* * * * *", timeout=10)
+
+graph.add_edges([flaky_request], [hangs])
+
+Executor(graphs=[graph]).start()
@@ -0,0 +1,23 @@
+#![no_main]
+
+use chrono::{DateTime, Duration, Utc};
+use libfuzzer_sys::fuzz_target;
+use tm::cron::expression::Expression;
+
+// Random strings rarely spell out `@every` with a large number,
+// so build the intervals from parts, each any u64 with a unit
+fuzz_target!(|parts: Vec<(u64, u8)>| {
+    let interval: String = parts
+        .iter()
+        .map(|(n, unit)| format!("{}{}", n, ['h', 'm', 's'][*unit as usize % 3]))
+        .collect();
+    let Ok(expression) = Expression::from_str(&format!("@every {}", interval)) else {
+        return;
+    };
+
+    let now = DateTime::UNIX_EPOCH + Duration::days(20_000);
+    for now in [now, DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC] {
+        expression.next_utc(now);
+        expression.prev_utc(now);
+    }
+});
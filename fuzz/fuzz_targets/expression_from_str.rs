@@ -0,0 +1,27 @@
+#![no_main]
+
+use chrono::{DateTime, Duration, Utc};
+use libfuzzer_sys::fuzz_target;
+use tm::cron::expression::Expression;
+
+// Parsing any input must return a value or an error, never panic,
+// and so must using the expressions that parse, also from the ends of time
+// where searches return None instead of overflowing
+fuzz_target!(|expression: &str| {
+    let Ok(expression) = Expression::from_str(expression) else {
+        return;
+    };
+
+    expression.to_string();
+    expression.describe();
+
+    let now = DateTime::UNIX_EPOCH + Duration::days(20_000);
+    for now in [now, DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC] {
+        expression.prev_utc(now);
+        expression.matches_utc(now);
+        if let Some(next) = expression.next_utc(now) {
+            expression.prev_utc(next);
+            expression.matches_utc(next);
+        }
+    }
+});
@@ -0,0 +1,397 @@
+use super::helpers::{local_time, localize, parse_holiday_policy};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+// A calendar without a single business day in this many days excludes everything
+const MAX_DAYS: i64 = 366;
+// last year schedules support, where endless recurrence rules stop
+const LAST_YEAR: i32 = 2099;
+
+/// What happens to a fire time that falls on a holiday or a weekend
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HolidayPolicy {
+    /// The fire time is dropped
+    #[default]
+    Skip,
+    /// The fire time moves to the same wall-clock time on the next business day
+    Shift,
+}
+
+/// Days a schedule must not fire on, the holidays and optionally the weekends
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone, Debug, Default)]
+pub struct Calendar {
+    holidays: BTreeSet<NaiveDate>,
+    // Saturdays and Sundays aren't business days
+    weekends: bool,
+    policy: HolidayPolicy,
+}
+
+// holiday files are a list of dates, or an object with a `holidays` list,
+// where every date is either a string or an object with a `date`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonHolidays {
+    List(Vec<JsonHoliday>),
+    Object { holidays: Vec<JsonHoliday> },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonHoliday {
+    Date(String),
+    Named { date: String },
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Calendar {
+    #[new]
+    #[pyo3(signature = (holidays = None, weekends = true, on_holiday = "skip"))]
+    fn py_new(holidays: Option<Vec<NaiveDate>>, weekends: bool, on_holiday: &str) -> Result<Self> {
+        Calendar::new(holidays.unwrap_or_default())
+            .with_weekends(weekends)
+            .with_holiday_policy(on_holiday)
+    }
+
+    /// Loads the holidays of an `.ics` or `.json` file
+    #[staticmethod]
+    #[pyo3(name = "from_file", signature = (path, weekends = true, on_holiday = "skip"))]
+    fn py_from_file(path: &str, weekends: bool, on_holiday: &str) -> Result<Self> {
+        Calendar::from_file(path)?
+            .with_weekends(weekends)
+            .with_holiday_policy(on_holiday)
+    }
+
+    #[pyo3(name = "is_business_day")]
+    fn py_is_business_day(&self, date: NaiveDate) -> bool {
+        self.is_business_day(date)
+    }
+
+    #[pyo3(name = "next_business_day")]
+    fn py_next_business_day(&self, date: NaiveDate) -> Option<NaiveDate> {
+        self.next_business_day(date)
+    }
+
+    fn __repr__(&self) -> String {
+        self.repr()
+    }
+}
+
+impl Calendar {
+    /// Business days are weekdays that aren't `holidays`, fire times on other days are skipped
+    pub fn new(holidays: impl IntoIterator<Item = NaiveDate>) -> Self {
+        Calendar {
+            holidays: holidays.into_iter().collect(),
+            weekends: true,
+            policy: HolidayPolicy::Skip,
+        }
+    }
+
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        let weekend = self.weekends && matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        !weekend && !self.holidays.contains(&date)
+    }
+
+    /// First business day on or after `date`
+    pub fn next_business_day(&self, date: NaiveDate) -> Option<NaiveDate> {
+        date.iter_days()
+            .take(MAX_DAYS as _)
+            .find(|date| self.is_business_day(*date))
+    }
+
+    /// Python expression that builds a calendar like this one, without listing the holidays
+    pub fn repr(&self) -> String {
+        let policy = match self.policy {
+            HolidayPolicy::Skip => "skip",
+            HolidayPolicy::Shift => "shift",
+        };
+        format!(
+            "Calendar({} holidays, weekends={}, on_holiday=\"{}\")",
+            self.holidays.len(),
+            if self.weekends { "True" } else { "False" },
+            policy
+        )
+    }
+
+    /// Reads the dates of an `.ics` file's events or of a `.json` list of dates
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Can't read calendar {}", path.display()))?;
+
+        let holidays = match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("ics") => parse_ics(&content),
+            Some(e) if e.eq_ignore_ascii_case("json") => parse_json(&content),
+            _ => Err(anyhow!("Calendars must be .ics or .json files")),
+        }
+        .with_context(|| format!("Invalid calendar {}", path.display()))?;
+
+        Ok(Calendar::new(holidays))
+    }
+
+    /// Whether Saturdays and Sundays are non-business days too
+    pub fn with_weekends(mut self, weekends: bool) -> Self {
+        self.weekends = weekends;
+        self
+    }
+
+    /// `skip` drops fire times on non-business days, `shift` moves them to the next business day
+    pub fn with_holiday_policy(mut self, policy: &str) -> Result<Self> {
+        self.policy = parse_holiday_policy(policy)?;
+        Ok(self)
+    }
+
+    /// First fire time of `next` after `now` that this calendar allows,
+    /// with business days read in `tz`
+    pub fn next_utc(
+        &self,
+        tz: Tz,
+        now: DateTime<Utc>,
+        next: impl Fn(DateTime<Utc>) -> Option<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        match self.policy {
+            HolidayPolicy::Skip => {
+                let mut cursor = now;
+                loop {
+                    let time = next(cursor)?;
+                    let date = local_date(tz, time);
+                    if self.is_business_day(date) {
+                        return Some(time);
+                    }
+                    cursor = before(midnight(tz, self.next_business_day(date)?));
+                }
+            }
+            HolidayPolicy::Shift => {
+                // fire times on the non-business days right before today may have
+                // moved past `now`, and a shifted time is never earlier than the original
+                let start = self.run_start(local_date(tz, now));
+                let mut cursor = before(midnight(tz, start));
+                let mut best: Option<DateTime<Utc>> = None;
+
+                while let Some(time) = next(cursor) {
+                    if best.is_some_and(|best| time >= best) {
+                        break;
+                    }
+                    let shifted = self.shift(tz, time)?;
+                    if shifted > now && best.is_none_or(|best| shifted < best) {
+                        best = Some(shifted);
+                    }
+                    cursor = time;
+                }
+
+                best
+            }
+        }
+    }
+
+    /// Last fire time of `prev` before `now` that this calendar allows, mirrors `next_utc`
+    pub fn prev_utc(
+        &self,
+        tz: Tz,
+        now: DateTime<Utc>,
+        prev: impl Fn(DateTime<Utc>) -> Option<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        match self.policy {
+            HolidayPolicy::Skip => {
+                let mut cursor = now;
+                loop {
+                    let time = prev(cursor)?;
+                    let date = local_date(tz, time);
+                    if self.is_business_day(date) {
+                        return Some(time);
+                    }
+                    cursor = midnight(tz, self.run_start(date));
+                }
+            }
+            HolidayPolicy::Shift => {
+                let mut cursor = now;
+                let mut best: Option<DateTime<Utc>> = None;
+
+                while let Some(time) = prev(cursor) {
+                    // only the non-business days right before a business day move to it
+                    if best.is_some_and(|best| {
+                        local_date(tz, time) < self.run_start(local_date(tz, best))
+                    }) {
+                        break;
+                    }
+                    let shifted = self.shift(tz, time)?;
+                    if shifted < now && best.is_none_or(|best| shifted > best) {
+                        best = Some(shifted);
+                    }
+                    cursor = time;
+                }
+
+                best
+            }
+        }
+    }
+
+    // Same wall-clock time on the next business day, or `time` if it's one
+    fn shift(&self, tz: Tz, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = local_time(tz, time);
+        let date = self.next_business_day(local.date())?;
+        if date == local.date() {
+            return Some(time);
+        }
+        Some(localize(tz, date.and_time(local.time())).with_timezone(&Utc))
+    }
+
+    // First of the non-business days right before `date`, `date` if there are none
+    fn run_start(&self, date: NaiveDate) -> NaiveDate {
+        let mut start = date;
+        while let Some(prev) = start.pred_opt() {
+            if self.is_business_day(prev) || date - prev > Duration::days(MAX_DAYS) {
+                break;
+            }
+            start = prev;
+        }
+        start
+    }
+}
+
+fn local_date(tz: Tz, time: DateTime<Utc>) -> NaiveDate {
+    local_time(tz, time).date()
+}
+
+// searches from `time` include it
+fn before(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.checked_sub_signed(Duration::nanoseconds(1))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+fn midnight(tz: Tz, date: NaiveDate) -> DateTime<Utc> {
+    localize(tz, date.and_time(NaiveTime::MIN)).with_timezone(&Utc)
+}
+
+// Dates of all-day events span DTSTART up to the exclusive DTEND, timed events
+// only cover the day they start. Yearly recurrence rules are expanded, any other
+// rule is an error rather than a holiday silently kept only once
+fn parse_ics(content: &str) -> Result<Vec<NaiveDate>> {
+    // long lines are folded with a leading space or tab on the continuation lines
+    let mut lines: Vec<String> = vec![];
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.trim_end().to_string()),
+        }
+    }
+
+    let mut holidays = vec![];
+    // DTSTART, DTEND and RRULE of the event being read
+    let mut event: Option<(Option<IcsDate>, Option<IcsDate>, Option<String>)> = None;
+
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // properties may carry parameters, like DTSTART;VALUE=DATE:20241225
+        let name = name.split(';').next().unwrap_or_default().to_uppercase();
+
+        match (name.as_str(), &mut event) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                event = Some((None, None, None))
+            }
+            ("DTSTART", Some((start, _, _))) => *start = Some(parse_ics_date(value)?),
+            ("DTEND", Some((_, end, _))) => *end = Some(parse_ics_date(value)?),
+            ("RRULE", Some((_, _, rule))) => *rule = Some(value.to_string()),
+            ("END", Some((start, end, rule))) if value.eq_ignore_ascii_case("VEVENT") => {
+                let (start, all_day) = start.ok_or_else(|| anyhow!("Event without DTSTART"))?;
+                // days the event covers past its start
+                let days = match end {
+                    Some((end, true)) if all_day && *end > start => (*end - start).num_days(),
+                    _ => 1,
+                };
+                let starts = match rule {
+                    Some(rule) => parse_rrule(rule, start)?,
+                    None => vec![start],
+                };
+                for start in starts {
+                    holidays.extend(start.iter_days().take(days as usize));
+                }
+                event = None;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(holidays)
+}
+
+// Start dates of a yearly RRULE, bounded by COUNT, UNTIL or the last supported
+// year. Dates that don't exist in a year, like February 29, are skipped
+fn parse_rrule(rule: &str, start: NaiveDate) -> Result<Vec<NaiveDate>> {
+    let unsupported = || {
+        anyhow!(
+            "Unsupported recurrence rule {}, only FREQ=YEARLY is expanded",
+            rule
+        )
+    };
+
+    let (mut yearly, mut interval, mut count, mut until) = (false, 1, None, None);
+    for part in rule.trim().split(';').filter(|part| !part.is_empty()) {
+        let (key, value) = part.split_once('=').ok_or_else(unsupported)?;
+        let number = || {
+            value
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(unsupported)
+        };
+        match key.to_uppercase().as_str() {
+            "FREQ" => yearly = value.eq_ignore_ascii_case("YEARLY"),
+            "INTERVAL" => interval = number()?,
+            "COUNT" => count = Some(number()? as usize),
+            "UNTIL" => until = Some(parse_ics_date(value)?.0),
+            // the defaults for a yearly rule, spelled out
+            "BYMONTH" if number()? == start.month() => (),
+            "BYMONTHDAY" if number()? == start.day() => (),
+            "WKST" => (),
+            _ => return Err(unsupported()),
+        }
+    }
+    if !yearly {
+        return Err(unsupported());
+    }
+
+    Ok((start.year()..=LAST_YEAR)
+        .step_by(interval as usize)
+        .filter_map(|year| start.with_year(year))
+        .take_while(|date| until.is_none_or(|until| *date <= until))
+        .take(count.unwrap_or(usize::MAX))
+        .collect())
+}
+
+// date of an ICS value, and whether the value was a date rather than a datetime
+type IcsDate = (NaiveDate, bool);
+
+fn parse_ics_date(value: &str) -> Result<IcsDate> {
+    let value = value.trim();
+    let date = value
+        .get(..8)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .ok_or_else(|| anyhow!("Invalid date {}", value))?;
+    Ok((date, value.len() == 8))
+}
+
+fn parse_json(content: &str) -> Result<Vec<NaiveDate>> {
+    let holidays = match serde_json::from_str(content)? {
+        JsonHolidays::List(holidays) | JsonHolidays::Object { holidays } => holidays,
+    };
+
+    holidays
+        .into_iter()
+        .map(|holiday| match holiday {
+            JsonHoliday::Date(date) | JsonHoliday::Named { date } => {
+                NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|_| anyhow!("Invalid date {}, expected YYYY-MM-DD", date))
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,344 @@
+use super::calendar::Calendar;
+#[cfg(feature = "python")]
+use super::error::CronError;
+use super::expression::Expression;
+#[cfg(feature = "python")]
+use super::expression::Moment;
+use super::helpers::parse_timezone;
+use super::schedule::ALL_SECONDS;
+use anyhow::Result;
+#[cfg(feature = "python")]
+use chrono::TimeZone;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+// Intersections and exclusions search fire by fire, give up on combinations
+// that can't fire within this many years, like `0 0 * * *` & `30 0 * * *`
+const HORIZON_YEARS: i64 = 50;
+
+// or within this many fire times, like `0 * * * * *` & `30 * * * * *`
+const MAX_STEPS: usize = 100_000;
+
+#[derive(Clone, Debug)]
+enum Node {
+    Expression(Box<Expression>),
+    Union(Vec<Node>),
+    Intersect(Vec<Node>),
+    Except(Box<Node>, Box<Node>),
+    // business days are read in the timezone
+    Calendar(Box<Node>, Calendar, Tz),
+}
+
+/// Union, intersection and exclusion of expressions, each evaluated in its own timezone
+#[cfg_attr(feature = "python", pyclass(name = "Schedule"))]
+#[derive(Clone, Debug)]
+pub struct Composite {
+    node: Node,
+}
+
+/// Anything Python code can combine into a schedule
+// only lives until it's converted with `into_composite`
+#[cfg(feature = "python")]
+#[allow(clippy::large_enum_variant)]
+#[derive(FromPyObject)]
+pub enum Operand {
+    Composite(Composite),
+    Expression(Expression),
+    Str(String),
+}
+
+#[cfg(feature = "python")]
+impl Operand {
+    pub fn into_composite(self) -> Result<Composite, CronError> {
+        match self {
+            Operand::Composite(composite) => Ok(composite),
+            Operand::Expression(expression) => Ok(expression.into()),
+            Operand::Str(expression) => Ok(Expression::from_str(&expression)?.into()),
+        }
+    }
+}
+
+impl From<Expression> for Composite {
+    fn from(expression: Expression) -> Self {
+        Composite {
+            node: Node::Expression(Box::new(expression)),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Composite {
+    /// The union of all `expressions`
+    #[new]
+    fn new(expressions: Vec<Operand>) -> PyResult<Self> {
+        let mut expressions = expressions.into_iter();
+        let first = expressions
+            .next()
+            .ok_or(CronError::EmptySchedule)?
+            .into_composite()?;
+
+        expressions.try_fold(first, |schedule, other| {
+            Ok(schedule.union(other.into_composite()?))
+        })
+    }
+
+    #[pyo3(name = "union")]
+    fn py_union(&self, other: Operand) -> PyResult<Self> {
+        Ok(self.clone().union(other.into_composite()?))
+    }
+
+    #[pyo3(name = "intersect")]
+    fn py_intersect(&self, other: Operand) -> PyResult<Self> {
+        Ok(self.clone().intersect(other.into_composite()?))
+    }
+
+    #[pyo3(name = "except_")]
+    fn py_except(&self, other: Operand) -> PyResult<Self> {
+        Ok(self.clone().except(other.into_composite()?))
+    }
+
+    fn __or__(&self, other: Operand) -> PyResult<Self> {
+        self.py_union(other)
+    }
+
+    fn __and__(&self, other: Operand) -> PyResult<Self> {
+        self.py_intersect(other)
+    }
+
+    fn __sub__(&self, other: Operand) -> PyResult<Self> {
+        self.py_except(other)
+    }
+
+    #[pyo3(name = "with_calendar", signature = (calendar, timezone = None))]
+    fn py_with_calendar(&self, calendar: Calendar, timezone: Option<&str>) -> Result<Self> {
+        self.clone().with_calendar(calendar, timezone)
+    }
+
+    /// Naive datetimes are treated and returned as UTC, since the expressions
+    /// may be in different timezones. Aware datetimes are returned in UTC
+    #[pyo3(name = "next")]
+    fn py_next(&self, py: Python<'_>, now: Moment) -> Option<PyObject> {
+        let (now, aware) = match now {
+            Moment::Aware(now) => (now.with_timezone(&Utc), true),
+            Moment::Naive(now) => (Utc.from_utc_datetime(&now), false),
+        };
+
+        let next = self.next_utc(now)?;
+        match aware {
+            true => Some(next.into_py(py)),
+            false => Some(next.naive_utc().into_py(py)),
+        }
+    }
+
+    #[pyo3(name = "matches")]
+    fn py_matches(&self, time: Moment) -> bool {
+        match time {
+            Moment::Aware(time) => self.matches_utc(time.with_timezone(&Utc)),
+            Moment::Naive(time) => self.matches_utc(Utc.from_utc_datetime(&time)),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        self.repr()
+    }
+}
+
+impl Composite {
+    /// Fires when either schedule fires
+    pub fn union(self, other: Composite) -> Self {
+        let mut nodes = match self.node {
+            Node::Union(nodes) => nodes,
+            node => vec![node],
+        };
+        nodes.push(other.node);
+        Composite {
+            node: Node::Union(nodes),
+        }
+    }
+
+    /// Fires when both schedules fire at the same time
+    pub fn intersect(self, other: Composite) -> Self {
+        let mut nodes = match self.node {
+            Node::Intersect(nodes) => nodes,
+            node => vec![node],
+        };
+        nodes.push(other.node);
+        Composite {
+            node: Node::Intersect(nodes),
+        }
+    }
+
+    /// Fires when this schedule fires and `other` doesn't
+    pub fn except(self, other: Composite) -> Self {
+        Composite {
+            node: Node::Except(Box::new(self.node), Box::new(other.node)),
+        }
+    }
+
+    /// Skip or shift the fire times on the calendar's non-business days,
+    /// read in `timezone` or UTC
+    pub fn with_calendar(self, calendar: Calendar, timezone: Option<&str>) -> Result<Self> {
+        let tz = match timezone {
+            Some(timezone) => parse_timezone(timezone)?,
+            None => Tz::UTC,
+        };
+        Ok(Composite {
+            node: Node::Calendar(Box::new(self.node), calendar, tz),
+        })
+    }
+
+    /// Python expression that builds the same schedule
+    pub fn repr(&self) -> String {
+        self.node.repr()
+    }
+
+    pub fn next_utc(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let horizon = now + Duration::days(HORIZON_YEARS * 366);
+        self.node.next(now, horizon)
+    }
+
+    pub fn matches_utc(&self, time: DateTime<Utc>) -> bool {
+        let horizon = time + Duration::days(HORIZON_YEARS * 366);
+        self.node.fires_at(time, horizon)
+    }
+}
+
+impl Node {
+    fn next(&self, now: DateTime<Utc>, horizon: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Node::Expression(expression) => expression.next_utc(now),
+            Node::Union(nodes) => nodes
+                .iter()
+                .filter_map(|node| node.next(now, horizon))
+                .min(),
+            Node::Intersect(nodes) => {
+                // nothing fires before the latest of the next fire times,
+                // move there until every schedule agrees
+                let mut now = now;
+                for _ in 0..MAX_STEPS {
+                    let nexts = nodes
+                        .iter()
+                        .map(|node| node.next(now, horizon))
+                        .collect::<Option<Vec<_>>>()?;
+                    let latest = nexts.iter().max().copied()?;
+
+                    if latest > horizon {
+                        return None;
+                    }
+                    if nexts.iter().all(|next| *next == latest) {
+                        return Some(latest);
+                    }
+                    now = latest - Duration::nanoseconds(1);
+                }
+                None
+            }
+            Node::Except(node, excluded) => {
+                let mut now = now;
+                for _ in 0..MAX_STEPS {
+                    let next = node.next(now, horizon)?;
+                    if next > horizon {
+                        return None;
+                    }
+                    if !excluded.fires_at(next, horizon) {
+                        return Some(next);
+                    }
+
+                    // skip the fire times the exclusion covers, only the seconds
+                    // `node` fires on matter, like second 0 for 5-field expressions
+                    let seconds = node.seconds(next);
+                    if excluded.fires_always(seconds) {
+                        return None;
+                    }
+                    now = match excluded.fires_until(next, seconds, horizon) {
+                        Some(until) if until > next => until - Duration::nanoseconds(1),
+                        _ => next,
+                    };
+                }
+                None
+            }
+            Node::Calendar(node, calendar, tz) => {
+                calendar.next_utc(*tz, now, |now| node.next(now, horizon))
+            }
+        }
+    }
+
+    // Bitmask of the seconds the schedule may fire on around `time`
+    fn seconds(&self, time: DateTime<Utc>) -> u64 {
+        match self {
+            Node::Expression(expression) => expression.seconds(time),
+            Node::Union(nodes) => nodes
+                .iter()
+                .fold(0, |seconds, node| seconds | node.seconds(time)),
+            Node::Intersect(nodes) => nodes
+                .iter()
+                .fold(ALL_SECONDS, |seconds, node| seconds & node.seconds(time)),
+            Node::Except(node, _) => node.seconds(time),
+            // shifted to other days at the same time
+            Node::Calendar(node, ..) => node.seconds(time),
+        }
+    }
+
+    // Whether the schedule fires at every time whose second is in `seconds`,
+    // false when unsure
+    fn fires_always(&self, seconds: u64) -> bool {
+        match self {
+            Node::Expression(expression) => expression.fires_always(seconds),
+            Node::Union(nodes) => nodes.iter().any(|node| node.fires_always(seconds)),
+            Node::Intersect(nodes) => nodes.iter().all(|node| node.fires_always(seconds)),
+            Node::Except(..) | Node::Calendar(..) => false,
+        }
+    }
+
+    // End of the fire times from `time`, a fire time, like `Expression::fires_until`.
+    // A union fires until the last of its schedules firing at `time` stops, an
+    // intersection until the first of its schedules does
+    fn fires_until(
+        &self,
+        time: DateTime<Utc>,
+        seconds: u64,
+        horizon: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        match self {
+            Node::Expression(expression) => expression.fires_until(time, seconds),
+            Node::Union(nodes) => nodes
+                .iter()
+                .filter(|node| node.fires_at(time, horizon))
+                .filter_map(|node| node.fires_until(time, seconds, horizon))
+                .max(),
+            Node::Intersect(nodes) => nodes
+                .iter()
+                .map(|node| node.fires_until(time, seconds, horizon))
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .min(),
+            Node::Except(..) | Node::Calendar(..) => None,
+        }
+    }
+
+    fn fires_at(&self, time: DateTime<Utc>, horizon: DateTime<Utc>) -> bool {
+        self.next(time - Duration::nanoseconds(1), horizon) == Some(time)
+    }
+
+    fn repr(&self) -> String {
+        let operand = |node: &Node| match node {
+            Node::Expression(expression) => expression.repr(),
+            node => format!("({})", node.repr()),
+        };
+
+        match self {
+            Node::Expression(expression) => expression.repr(),
+            Node::Union(nodes) => nodes.iter().map(operand).collect::<Vec<_>>().join(" | "),
+            Node::Intersect(nodes) => nodes.iter().map(operand).collect::<Vec<_>>().join(" & "),
+            Node::Except(node, excluded) => format!("{} - {}", operand(node), operand(excluded)),
+            Node::Calendar(node, calendar, tz) => format!(
+                "{}.with_calendar({}, timezone=\"{}\")",
+                operand(node),
+                calendar.repr(),
+                tz.name()
+            ),
+        }
+    }
+}
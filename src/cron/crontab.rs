@@ -0,0 +1,149 @@
+use super::expression::Expression;
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// A crontab line that runs a command
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// `<file name>:<line number>`, also the key `H` tokens are hashed from
+    pub name: String,
+    pub expression: Expression,
+    /// Only system crontabs, like /etc/crontab, have a user column
+    pub user: Option<String>,
+    /// Runs with `$SHELL -c`, `sh` unless the crontab sets `SHELL`
+    pub command: String,
+    /// Text after the first unescaped `%`, with the other `%` as newlines
+    pub input: Option<String>,
+    /// Variables assigned above the entry, like `SHELL` and `MAILTO`
+    pub env: BTreeMap<String, String>,
+}
+
+/// Reads a crontab file, see `parse`
+pub fn from_file(path: impl AsRef<Path>, user_column: bool) -> Result<Vec<Entry>> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Can't read crontab {}", path.display()))?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    parse(&name, &content, user_column)
+}
+
+/// Entries of a crontab named `name`, like Vixie cron reads them. Assignments
+/// apply to the entries below them, and `CRON_TZ` sets their timezone
+pub fn parse(name: &str, content: &str, user_column: bool) -> Result<Vec<Entry>> {
+    let mut env = BTreeMap::new();
+    let mut entries = vec![];
+
+    for (number, line) in content.lines().enumerate() {
+        let name = format!("{}:{}", name, number + 1);
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some((key, value)) = assignment(line) {
+            env.insert(key, value);
+            continue;
+        }
+
+        let entry = parse_entry(&name, line, user_column, &env)
+            .with_context(|| format!("Invalid crontab entry {}", name))?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+// `NAME = value`, where the value may be quoted
+fn assignment(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let value = value.trim();
+    let value = ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value);
+    Some((key.into(), value.into()))
+}
+
+fn parse_entry(
+    name: &str,
+    line: &str,
+    user_column: bool,
+    env: &BTreeMap<String, String>,
+) -> Result<Entry> {
+    let fields = match line.split_whitespace().next() {
+        Some(word) if word.eq_ignore_ascii_case("@reboot") => {
+            return Err(anyhow!(
+                "@reboot isn't supported, graphs only run on schedules"
+            ));
+        }
+        Some(word) if word.eq_ignore_ascii_case("@every") => 2,
+        Some(word) if word.starts_with('@') => 1,
+        _ => 5,
+    };
+
+    let (schedule, rest) = split_words(line, fields);
+    let (user, rest) = match user_column {
+        true => {
+            let (user, rest) = split_words(rest, 1);
+            (Some(user.to_string()), rest)
+        }
+        false => (None, rest),
+    };
+    if rest.is_empty() {
+        return Err(anyhow!("Entry has no command"));
+    }
+
+    let mut expression = Expression::from_str_with_key(schedule, name)?;
+    if let Some(timezone) = env.get("CRON_TZ") {
+        expression = expression.with_timezone(timezone)?;
+    }
+
+    let (command, input) = split_input(rest);
+    Ok(Entry {
+        name: name.into(),
+        expression,
+        user,
+        command,
+        input,
+        env: env.clone(),
+    })
+}
+
+// The first `count` words, and the trimmed rest of the line
+fn split_words(line: &str, count: usize) -> (&str, &str) {
+    let mut end = 0;
+    for _ in 0..count {
+        let start = end + line[end..].len() - line[end..].trim_start().len();
+        end = line[start..]
+            .find(char::is_whitespace)
+            .map_or(line.len(), |len| start + len);
+    }
+    (line[..end].trim(), line[end..].trim())
+}
+
+// The command and its input, `\%` is a literal `%`
+fn split_input(command: &str) -> (String, Option<String>) {
+    let mut parts = vec![String::new()];
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.next_if_eq(&'%').is_some() => parts.last_mut().unwrap().push('%'),
+            '%' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+
+    let command = parts.remove(0);
+    let input = (!parts.is_empty()).then(|| parts.join("\n"));
+    (command.trim_end().into(), input)
+}
@@ -0,0 +1,228 @@
+use super::expression::Expression;
+use super::schedule::DaySemantics;
+use super::unit::{Modifier, Part, Unit};
+use chrono::Duration;
+use itertools::Itertools;
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const ORDINALS: [&str; 5] = ["first", "second", "third", "fourth", "fifth"];
+
+impl Expression {
+    /// Renders the expression as an English sentence, e.g.
+    /// "At minutes 1, 2 and every 15 minutes past hour 0, on day 1 and on Tuesdays in March"
+    pub fn describe(&self) -> String {
+        if let Some(interval) = self.interval {
+            return format!("Every {}", describe_duration(interval));
+        }
+
+        let [second, minute, hour, dom, month, dow, year] = &self.fields;
+
+        let mut sentence = format!("At {}", describe_time(second, minute, hour));
+
+        let days = match (dom.as_str(), dow.as_str()) {
+            ("*", "*") => vec![],
+            (_, "*") => vec![describe_field(&Unit::Dom, dom)],
+            ("*", _) => vec![describe_field(&Unit::Dow, dow)],
+            (_, _) => vec![
+                describe_field(&Unit::Dom, dom),
+                describe_field(&Unit::Dow, dow),
+            ],
+        };
+        if !days.is_empty() {
+            let separator = match self.day_semantics() {
+                DaySemantics::Or => " and on ",
+                DaySemantics::And => " if it falls on ",
+            };
+            sentence += &format!(", on {}", days.join(separator));
+        }
+
+        if month != "*" {
+            sentence += &format!(" in {}", describe_field(&Unit::Month, month));
+        }
+
+        if year != "*" {
+            sentence += &format!(" in {}", describe_field(&Unit::Year, year));
+        }
+
+        if let Some(tz) = self.timezone {
+            sentence += &format!(" ({})", tz.name());
+        }
+
+        sentence
+    }
+}
+
+fn describe_time(second: &str, minute: &str, hour: &str) -> String {
+    let exact = |v: &str| v.parse::<u32>().ok();
+
+    if let (Some(s), Some(m), Some(h)) = (exact(second), exact(minute), exact(hour)) {
+        return match s {
+            0 => format!("{:02}:{:02}", h, m),
+            _ => format!("{:02}:{:02}:{:02}", h, m, s),
+        };
+    }
+
+    let mut parts = vec![];
+    if second != "0" {
+        parts.push(describe_field(&Unit::Second, second));
+    }
+    // every minute is implied by restricted seconds
+    if minute != "*" || parts.is_empty() {
+        parts.push(describe_field(&Unit::Minute, minute));
+    }
+    if hour != "*" {
+        parts.push(describe_field(&Unit::Hour, hour));
+    }
+
+    parts.join(" past ")
+}
+
+fn describe_field(unit: &Unit, field: &str) -> String {
+    if field == "*" {
+        return format!("every {}", noun(unit, false));
+    }
+
+    // standalone days of the week read as "Mondays"
+    let value = |v: &str| match unit {
+        Unit::Dow => format!("{}s", name(unit, v)),
+        _ => name(unit, v),
+    };
+
+    // plain values share a single noun, e.g. "minutes 1, 2 and 5"
+    let mut values = 0;
+    let mut plural = false;
+    let items = field
+        .split(',')
+        .flat_map(|part| {
+            if let Ok(Part::Modifier(modifier)) = unit.parse_part(part, 0) {
+                return vec![describe_modifier(&modifier)];
+            }
+
+            if let Some((range, step)) = part.split_once('/') {
+                let from = match range.split_once('-') {
+                    Some((l, r)) => format!(" from {} through {}", name(unit, l), name(unit, r)),
+                    None if range == "*" => String::new(),
+                    None => format!(" starting at {}", name(unit, range)),
+                };
+                return vec![format!("every {} {}{}", step, noun(unit, true), from)];
+            }
+
+            values += 1;
+            match part.split_once('-') {
+                Some((l, r)) if r.parse::<i32>().ok() == l.parse::<i32>().ok().map(|l| l + 1) => {
+                    plural = true;
+                    vec![value(l), value(r)]
+                }
+                Some((l, r)) => {
+                    plural = true;
+                    vec![format!("{} through {}", name(unit, l), name(unit, r))]
+                }
+                None => vec![value(part)],
+            }
+        })
+        .collect_vec();
+
+    let list = join(&items);
+    match unit {
+        // names carry their own meaning
+        Unit::Month | Unit::Dow => list,
+        _ if values == 0 => list,
+        _ => format!("{} {}", noun(unit, plural || values > 1), list),
+    }
+}
+
+fn describe_modifier(modifier: &Modifier) -> String {
+    match *modifier {
+        Modifier::LastDay(0) => "the last day of the month".into(),
+        Modifier::LastDay(1) => "1 day before the last day of the month".into(),
+        Modifier::LastDay(offset) => format!("{} days before the last day of the month", offset),
+        Modifier::LastWeekday => "the last weekday of the month".into(),
+        Modifier::NearestWeekday(day) => format!("the weekday nearest day {}", day),
+        Modifier::LastDow(dow) => format!("the last {} of the month", WEEKDAYS[dow as usize]),
+        Modifier::NthDow(dow, nth) => format!(
+            "the {} {} of the month",
+            ORDINALS[nth as usize - 1],
+            WEEKDAYS[dow as usize]
+        ),
+    }
+}
+
+fn describe_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds();
+    let parts = [
+        (seconds / 3600, "hour"),
+        (seconds % 3600 / 60, "minute"),
+        (seconds % 60, "second"),
+    ];
+
+    let parts = parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| match value {
+            1 => format!("1 {}", unit),
+            _ => format!("{} {}s", value, unit),
+        })
+        .collect_vec();
+
+    join(&parts)
+}
+
+fn noun(unit: &Unit, plural: bool) -> String {
+    let noun = match unit {
+        Unit::Second => "second",
+        Unit::Minute => "minute",
+        Unit::Hour => "hour",
+        Unit::Dom => "day",
+        Unit::Month => "month",
+        Unit::Dow => "day of the week",
+        Unit::Year => "year",
+    };
+
+    match (unit, plural) {
+        (Unit::Dow, true) => "days of the week".into(),
+        (_, true) => format!("{}s", noun),
+        (_, false) => noun.into(),
+    }
+}
+
+fn name(unit: &Unit, value: &str) -> String {
+    match (unit, value.parse::<usize>()) {
+        (Unit::Month, Ok(month)) => MONTHS[month - 1].into(),
+        // 7 is also Sunday
+        (Unit::Dow, Ok(dow)) => WEEKDAYS[dow % 7].into(),
+        _ => value.into(),
+    }
+}
+
+// "a", "a and b", "a, b and c"
+fn join(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [item] => item.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(", "), last),
+    }
+}
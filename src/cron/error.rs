@@ -0,0 +1,173 @@
+/// The field of a `Position`, displayed like `day of month`
+pub use super::unit::Unit;
+#[cfg(feature = "python")]
+use pyo3::{create_exception, exceptions::PyValueError, PyErr, Python};
+use std::fmt;
+
+#[cfg(feature = "python")]
+create_exception!(
+    cron,
+    CronParseError,
+    PyValueError,
+    "Invalid cron expression, `field`, `part` and `offset` locate the bad token when known"
+);
+
+/// Where a bad token is in the expression
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub field: Unit,
+    /// Index of the comma-separated part within the field
+    pub part: usize,
+    /// Character offset of the token within the whole expression
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CronError {
+    /// Expressions have 5 fields, 6 with seconds or 7 with seconds and years
+    FieldCount(usize),
+    UnknownMacro(String),
+    /// One-shot `at:` schedules take an ISO datetime between 1970 and 2099
+    InvalidDateTime(String),
+    InvalidInterval {
+        interval: String,
+        reason: &'static str,
+    },
+    /// Not a number, or a name that doesn't belong to the field
+    InvalidValue {
+        at: Position,
+        value: String,
+    },
+    OutOfRange {
+        at: Position,
+        value: i32,
+        min: i32,
+        max: i32,
+    },
+    /// Steps must be positive numbers
+    InvalidStep {
+        at: Position,
+        step: String,
+    },
+    /// Valid tokens in an order the grammar doesn't allow, like `1-` or `*-5`
+    InvalidPart {
+        at: Position,
+        part: String,
+    },
+    /// Every field is valid, but the fields never match together, like Feb-30
+    NeverFires,
+    /// Composite schedules need at least one expression
+    EmptySchedule,
+}
+
+impl CronError {
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            CronError::InvalidValue { at, .. }
+            | CronError::OutOfRange { at, .. }
+            | CronError::InvalidStep { at, .. }
+            | CronError::InvalidPart { at, .. } => Some(at),
+            _ => None,
+        }
+    }
+
+    /// Moves the position of a token found at the start of `part`, `offset` characters
+    /// into its field, to its place in the whole expression
+    pub(super) fn locate(mut self, part: usize, offset: usize) -> Self {
+        if let CronError::InvalidValue { at, .. }
+        | CronError::OutOfRange { at, .. }
+        | CronError::InvalidStep { at, .. }
+        | CronError::InvalidPart { at, .. } = &mut self
+        {
+            at.part = part;
+            at.offset += offset;
+        }
+        self
+    }
+}
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CronError::FieldCount(count) => {
+                write!(f, "Expression should have 5 to 7 fields, found {}", count)
+            }
+            CronError::UnknownMacro(name) => write!(f, "Unknown macro {}", name),
+            CronError::InvalidDateTime(at) => write!(
+                f,
+                "Datetime {} is not valid, expected an ISO datetime like 2026-12-31T23:00",
+                at
+            ),
+            CronError::InvalidInterval { interval, reason } => {
+                write!(f, "Interval {} {}", interval, reason)
+            }
+            CronError::InvalidValue { at, value } => {
+                write!(f, "Value {} is not valid in field {}", value, at.field)
+            }
+            CronError::OutOfRange {
+                at,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "Value {} for field {} must be between {} and {}",
+                value, at.field, min, max
+            ),
+            CronError::InvalidStep { at, step } => {
+                write!(
+                    f,
+                    "Step {} in field {} must be a positive number",
+                    step, at.field
+                )
+            }
+            CronError::InvalidPart { at, part } => {
+                write!(f, "Part {} is not valid in field {}", part, at.field)
+            }
+            CronError::NeverFires => write!(f, "Expression never fires"),
+            CronError::EmptySchedule => write!(f, "Schedule needs at least one expression"),
+        }?;
+
+        match self.position() {
+            Some(at) => write!(f, " (part {}, offset {})", at.part, at.offset),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for CronError {}
+
+#[cfg(feature = "python")]
+impl From<CronError> for PyErr {
+    fn from(error: CronError) -> Self {
+        cron_parse_error(error.to_string(), error.position())
+    }
+}
+
+/// Errors caused by a `CronError` raise `CronParseError` with their context, like the
+/// crontab line, other errors raise a `RuntimeError` like any anyhow error
+#[cfg(feature = "python")]
+pub fn to_py_err(error: anyhow::Error) -> PyErr {
+    match error.downcast_ref::<CronError>() {
+        Some(cron) => cron_parse_error(format!("{:#}", error), cron.position()),
+        None => error.into(),
+    }
+}
+
+#[cfg(feature = "python")]
+fn cron_parse_error(message: String, at: Option<&Position>) -> PyErr {
+    Python::with_gil(|py| {
+        let err = CronParseError::new_err(message);
+        let value = err.value(py);
+        // attributes are None when the error isn't about a single token
+        let attributes = value
+            .setattr("field", at.map(|at| at.field.to_string()))
+            .and_then(|_| value.setattr("part", at.map(|at| at.part)))
+            .and_then(|_| value.setattr("offset", at.map(|at| at.offset)));
+
+        match attributes {
+            Ok(_) => err,
+            Err(e) => e,
+        }
+    })
+}
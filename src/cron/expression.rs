@@ -1,174 +1,497 @@
-use super::helpers::{adjust_days_to_month, get_next, next_month, Schedule};
+use super::calendar::Calendar;
+#[cfg(feature = "python")]
+use super::composite::{Composite, Operand};
+use super::error::CronError;
+use super::helpers::{
+    expand_macro, local_time, localize, parse_at, parse_day_semantics, parse_interval,
+    parse_timezone, seed, split_fields,
+};
+use super::iter::FireTimes;
+use super::schedule::{DaySemantics, Schedule, ALL_SECONDS};
 use super::unit::Unit;
-use anyhow::{anyhow, Error, Result};
-use chrono::{Datelike, NaiveDateTime, Utc};
-use itertools::Itertools;
+use anyhow::Result;
+#[cfg(feature = "python")]
+use chrono::FixedOffset;
+use chrono::{DateTime, Duration, NaiveDateTime, Offset, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
-use std::collections::HashMap;
+use std::fmt;
 
-const DOM: usize = 2;
-const DOW: usize = 4;
-
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 #[derive(Debug, Clone)]
 pub struct Expression {
-    pub fields: [String; 5],
+    pub fields: [String; 7],
+    // fixed delay of `@every` expressions, fields are unused
+    pub interval: Option<Duration>,
+    // fields are evaluated in this zone's wall-clock time, UTC if not set
+    pub timezone: Option<Tz>,
+    // days the expression must not fire on, read in its timezone
+    pub calendar: Option<Calendar>,
+    schedule: Schedule,
+}
+
+/// A naive or timezone-aware Python datetime
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum Moment {
+    Aware(DateTime<FixedOffset>),
+    Naive(NaiveDateTime),
+}
+
+/// Canonical form of the expression, years are omitted when every year is allowed
+/// and then seconds when they are always 0, since a year needs the seconds field
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(interval) = self.interval {
+            let seconds = interval.num_seconds();
+            let units = [
+                (seconds / 3600, 'h'),
+                (seconds % 3600 / 60, 'm'),
+                (seconds % 60, 's'),
+            ];
+            write!(f, "@every ")?;
+            for (value, unit) in units.iter().filter(|(value, _)| *value > 0) {
+                write!(f, "{}{}", value, unit)?;
+            }
+            return Ok(());
+        }
+
+        // fields are already canonical, see `Unit::canonical`
+        let mut fields = &self.fields[..];
+        if let [second, rest @ .., year] = fields {
+            if year == "*" {
+                fields = &fields[..fields.len() - 1];
+                if second == "0" {
+                    fields = rest;
+                }
+            }
+        }
+        write!(f, "{}", fields.join(" "))
+    }
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl Expression {
     #[new]
-    pub fn from_str(expression: &str) -> Result<Self, Error> {
-        let fields: [String; 5] = expression
-            .split_whitespace()
-            .map(|s| s.to_string())
-            .collect_vec()
-            .try_into()
-            .map_err(|_| anyhow!("Expression {} should have 5 fields", expression))?;
+    #[pyo3(signature = (
+        expression,
+        timezone = None,
+        day_semantics = None,
+        calendar = None,
+        hash_key = None
+    ))]
+    fn new(
+        expression: &str,
+        timezone: Option<&str>,
+        day_semantics: Option<&str>,
+        calendar: Option<Calendar>,
+        hash_key: Option<&str>,
+    ) -> PyResult<Self> {
+        let mut expression = Self::from_str_with_key(expression, hash_key.unwrap_or_default())?;
+        if let Some(timezone) = timezone {
+            expression = expression.with_timezone(timezone)?;
+        }
+        if let Some(day_semantics) = day_semantics {
+            expression = expression.with_day_semantics(day_semantics)?;
+        }
+        if let Some(calendar) = calendar {
+            expression = expression.with_calendar(calendar);
+        }
+        Ok(expression)
+    }
 
-        let fields = Unit::parse_to_numeric(fields);
+    /// Aware datetimes are returned in the expression's timezone,
+    /// naive datetimes are treated as wall-clock times in that timezone
+    #[pyo3(name = "next")]
+    fn py_next(&self, py: Python<'_>, now: Moment) -> Option<PyObject> {
+        self.evaluate(py, now, Self::next_utc)
+    }
 
-        let e = Expression { fields };
-        e.validate()?;
+    /// Last fire time strictly before `now`, same conversions as `next`
+    #[pyo3(name = "prev")]
+    fn py_prev(&self, py: Python<'_>, now: Moment) -> Option<PyObject> {
+        self.evaluate(py, now, Self::prev_utc)
+    }
 
-        Ok(e)
+    #[pyo3(name = "describe")]
+    fn py_describe(&self) -> String {
+        self.describe()
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        self.repr()
+    }
+
+    /// `expression | other` fires when either fires, see `Composite`
+    fn __or__(&self, other: Operand) -> PyResult<Composite> {
+        Ok(Composite::from(self.clone()).union(other.into_composite()?))
+    }
+
+    /// `expression & other` fires when both fire at the same time
+    fn __and__(&self, other: Operand) -> PyResult<Composite> {
+        Ok(Composite::from(self.clone()).intersect(other.into_composite()?))
     }
 
-    pub fn next(&self, now: NaiveDateTime) -> NaiveDateTime {
-        // errors checked during initialization, can safely unwrap
-        let schedule = self.create_schedule(now.year(), now.month() as _).unwrap();
-        let (next, _) =
-            Self::calculate_next_time(Unit::Year, false, &schedule, Unit::to_hash(now)).unwrap();
-        Unit::from_hash(next)
+    /// `expression - other` fires when the expression fires and `other` doesn't
+    fn __sub__(&self, other: Operand) -> PyResult<Composite> {
+        Ok(Composite::from(self.clone()).except(other.into_composite()?))
+    }
+
+    #[pyo3(name = "matches")]
+    fn py_matches(&self, time: Moment) -> bool {
+        let (time, _) = self.to_utc(time);
+        self.matches_utc(time)
+    }
+
+    #[pyo3(name = "iter_from")]
+    fn py_iter_from(&self, start: Moment) -> FireTimes {
+        let (start, aware) = self.to_utc(start);
+        self.iter_from(start).aware(aware)
+    }
+
+    #[pyo3(name = "between")]
+    fn py_between(&self, start: Moment, end: Moment) -> FireTimes {
+        let (start, aware) = self.to_utc(start);
+        let (end, _) = self.to_utc(end);
+        self.between(start, end).aware(aware)
+    }
+
+    #[pyo3(name = "count_between")]
+    fn py_count_between(&self, start: Moment, end: Moment) -> usize {
+        let (start, _) = self.to_utc(start);
+        let (end, _) = self.to_utc(end);
+        self.count_between(start, end)
     }
 }
 
 impl Expression {
-    fn validate(&self) -> Result<()> {
-        let now = Utc::now().naive_utc();
-        let schedule = self.create_schedule(now.year(), now.month() as _)?;
-        Self::calculate_next_time(Unit::Year, false, &schedule, Unit::to_hash(now))?;
-        Ok(())
+    /// Python expression that builds the same expression
+    pub fn repr(&self) -> String {
+        let mut repr = format!("Expression(\"{}\"", self);
+        if let Some(tz) = self.timezone {
+            repr += &format!(", timezone=\"{}\"", tz.name());
+        }
+        if self.day_semantics() == DaySemantics::And {
+            repr += ", day_semantics=\"and\"";
+        }
+        if let Some(calendar) = &self.calendar {
+            repr += &format!(", calendar={}", calendar.repr());
+        }
+        repr + ")"
     }
 
-    fn calculate_next_time(
-        unit: Unit,
-        reset: bool,
-        schedule: &Schedule,
-        time: HashMap<Unit, i32>,
-    ) -> Result<(HashMap<Unit, i32>, bool)> {
-        let mut time = time;
-        let mut schedule = schedule.clone();
-
-        // No days schedules for this month, keep incrementing months till we find some days
-        // the iterative approach helps with leap years
-        // If we go five years without finding a date, then the expression contains an invalid date
-        // like Feb-30 for example
-        let start_year = time[&Unit::Year];
-        while !schedule.contains_key(&Unit::Day) || schedule[&Unit::Day].is_empty() {
-            if time[&Unit::Year] == start_year + 5 {
-                return Err(anyhow!("Invalid Expression"));
-            }
-            time = next_month(time);
-            schedule = adjust_days_to_month(schedule, time[&Unit::Year], time[&Unit::Month])
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(expression: &str) -> Result<Self, CronError> {
+        Self::from_str_with_key(expression, "")
+    }
+
+    /// Parses an expression whose `H` tokens pick their values from a hash of `key`,
+    /// so the same expression under different keys fires at different, stable times
+    pub fn from_str_with_key(expression: &str, key: &str) -> Result<Self, CronError> {
+        if let Some(interval) = expression.trim().strip_prefix("@every ") {
+            return Ok(Expression {
+                fields: ["*"; 7].map(String::from),
+                interval: Some(parse_interval(interval.trim())?),
+                timezone: None,
+                calendar: None,
+                schedule: Schedule::default(),
+            });
         }
 
-        if unit == Unit::None {
-            return Ok((time, false));
+        if let Some(at) = expression.trim().strip_prefix("at:") {
+            return Self::from_str_with_key(&parse_at(at.trim())?, key);
         }
 
-        // A higher field changed its value, need to reset all lower fields
-        if reset {
-            return Self::calculate_next_time(
-                unit.next(),
-                reset,
-                &schedule,
-                unit.set(time, *schedule[&unit].first().unwrap()),
-            );
+        let expression = expand_macro(expression)?;
+        let mut fields = split_fields(expression);
+
+        // seconds field is optional, 5-field expressions fire at second 0
+        if fields.len() == 5 {
+            fields.insert(0, (0, "0"));
         }
 
-        // If minutes are not reset, then go to next value
-        if unit == Unit::Minute {
-            let (next_value, of) = get_next(&schedule[&unit], time[&unit]);
-            return Ok((unit.set(time, next_value), of));
+        // Quartz-style year field is optional, any year if missing
+        if fields.len() == 6 {
+            fields.push((expression.chars().count(), "*"));
         }
 
-        // unit is in schedule, increment only if lower fields overflow
-        if schedule[&unit].contains(&time[&unit]) {
-            let (mut time, of) = Self::calculate_next_time(unit.next(), false, &schedule, time)?;
-            if !of {
-                return Ok((time, of));
-            }
+        let fields: [(usize, &str); 7] = fields
+            .try_into()
+            .map_err(|fields: Vec<_>| CronError::FieldCount(fields.len()))?;
 
-            let (next_value, of) = get_next(&schedule[&unit], time[&unit]);
-            time = unit.set(time, next_value);
+        let offsets = fields.map(|(offset, _)| offset);
+        let fields = fields.map(|(_, field)| field.to_string());
 
-            // The month has changed, re-calculate the dom and dow
-            // and reset lower fields
-            if unit == Unit::Month {
-                schedule = adjust_days_to_month(schedule, time[&Unit::Year], time[&Unit::Month]);
-                (time, _) = Self::calculate_next_time(unit.next(), true, &schedule, time)?;
-            }
+        let e = Expression {
+            schedule: Schedule::new(&fields, offsets, seed(key))?,
+            fields: Unit::canonical_fields(&fields, seed(key)),
+            interval: None,
+            timezone: None,
+            calendar: None,
+        };
+        e.validate()?;
+
+        Ok(e)
+    }
+
+    /// Evaluate the expression in an IANA timezone, e.g. `Europe/Berlin`
+    pub fn with_timezone(mut self, timezone: &str) -> Result<Self> {
+        self.timezone = Some(parse_timezone(timezone)?);
+        Ok(self)
+    }
+
+    /// Combine a restricted day-of-month and day-of-week with `or` (classic cron) or `and`
+    pub fn with_day_semantics(mut self, day_semantics: &str) -> Result<Self> {
+        let day_semantics = parse_day_semantics(day_semantics)?;
+        self.schedule = self.schedule.with_day_semantics(day_semantics);
+        self.validate()?;
+        Ok(self)
+    }
 
-            return Ok((time, of));
+    pub fn day_semantics(&self) -> DaySemantics {
+        self.schedule.day_semantics()
+    }
+
+    /// Skip or shift the fire times that fall on the calendar's non-business days
+    pub fn with_calendar(mut self, calendar: Calendar) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    /// Next wall-clock time after `now`, ignoring the timezone.
+    /// None once the schedule is exhausted, like a one-shot `at:` in the past,
+    /// or when an `@every` interval goes past the supported dates
+    pub fn next(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        if let Some(interval) = self.interval {
+            return now.checked_add_signed(interval)?.with_nanosecond(0);
         }
 
-        // unit is not in schedule, increment and reset lower fields
-        if !schedule[&unit].contains(&time[&unit]) {
-            let (next_value, of) = get_next(&schedule[&unit], time[&unit]);
-            time = unit.set(time, next_value);
+        self.schedule.next_after(now)
+    }
 
-            // The month has changed, re-calculate the dom and dow
-            if unit == Unit::Month {
-                schedule = adjust_days_to_month(schedule, time[&Unit::Year], time[&Unit::Month]);
-            }
+    /// Previous wall-clock time before `now`, ignoring the timezone.
+    /// None if the schedule hasn't started yet
+    pub fn prev(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        if let Some(interval) = self.interval {
+            return now.checked_sub_signed(interval)?.with_nanosecond(0);
+        }
 
-            // reset lower fields
-            (time, _) = Self::calculate_next_time(unit.next(), true, &schedule, time)?;
-            return Ok((time, of));
+        self.schedule.prev_before(now)
+    }
+
+    /// Whether the wall-clock `time` is on the schedule, ignoring the timezone.
+    /// `@every` expressions are not anchored to the clock and never match
+    pub fn matches(&self, time: NaiveDateTime) -> bool {
+        self.interval.is_none() && self.schedule.matches(time)
+    }
+
+    /// Next fire time after `now`, evaluated in the expression's timezone.
+    ///
+    /// During DST transitions, wall-clock times skipped by the clocks moving
+    /// forward fire after the gap, shifted by its length (02:30 becomes 03:30),
+    /// and times repeated by the clocks moving back fire once, on their first occurrence.
+    /// Business days of the calendar are read in the same timezone
+    pub fn next_utc(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.calendar {
+            Some(calendar) => calendar.next_utc(self.tz(), now, |now| self.next_any_day(now)),
+            None => self.next_any_day(now),
         }
+    }
 
-        Ok((time, false))
+    /// Previous fire time before `now`, with the same DST and calendar policies as `next_utc`
+    pub fn prev_utc(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.calendar {
+            Some(calendar) => calendar.prev_utc(self.tz(), now, |now| self.prev_any_day(now)),
+            None => self.prev_any_day(now),
+        }
     }
 
-    fn create_schedule(&self, year: i32, month: i32) -> Result<Schedule> {
-        let mut schedule = HashMap::with_capacity(7); // 7 variants of Unit
-        let mut ignore = Vec::new();
+    /// Whether `next_utc` would fire at `time`, following the same DST and calendar policies
+    pub fn matches_utc(&self, time: DateTime<Utc>) -> bool {
+        match self.calendar {
+            Some(_) => self.next_utc(time - Duration::nanoseconds(1)) == Some(time),
+            None => self.matches_any_day(time),
+        }
+    }
 
-        schedule.insert(Unit::Year, (year..year + 20).collect_vec());
+    /// Bitmask of the seconds the expression fires on around `time`
+    pub fn seconds(&self, time: DateTime<Utc>) -> u64 {
+        match self.interval {
+            // wall-clock seconds only match UTC seconds with whole-minute offsets
+            None if self.offset(time) % 60 == 0 => self.schedule.seconds(),
+            _ => ALL_SECONDS,
+        }
+    }
 
-        // handle interaction with ranges
-        if self.fields[DOM] == "*" && self.fields[DOW] != "*" {
-            ignore.push(DOM);
+    /// End of the fire times from `time`, a fire time: every time before it whose
+    /// second is in `seconds` is a fire time too. Not later than the next midnight
+    /// or DST change, None for `@every` and calendar expressions and at the end of time
+    pub fn fires_until(&self, time: DateTime<Utc>, seconds: u64) -> Option<DateTime<Utc>> {
+        // `seconds` are UTC seconds, like the wall-clock ones with whole-minute offsets
+        if self.interval.is_some() || self.calendar.is_some() || self.offset(time) % 60 != 0 {
+            return None;
         }
-        if self.fields[DOW] == "*" && self.fields[DOM] != "*" {
-            ignore.push(DOW);
+
+        let local = local_time(self.tz(), time);
+        let until = time.checked_add_signed(self.schedule.next_miss(local, seconds)? - local)?;
+        if self.offset(until) == self.offset(time) {
+            return Some(until);
         }
 
-        for (index, field) in self.fields.iter().enumerate() {
-            if ignore.contains(&index) {
-                continue;
+        // wall-clock times only follow UTC until the offset changes
+        let (mut same, mut changed) = (time, until);
+        while changed - same > Duration::seconds(1) {
+            let middle = same + (changed - same) / 2;
+            match self.offset(middle) == self.offset(time) {
+                true => same = middle,
+                false => changed = middle,
             }
+        }
+        Some(same)
+    }
+
+    /// Whether the expression fires at every time whose second is in `seconds`.
+    /// Only in UTC, others don't fire again on the wall-clock times DST repeats
+    pub fn fires_always(&self, seconds: u64) -> bool {
+        self.interval.is_none()
+            && self.calendar.is_none()
+            && self.tz() == Tz::UTC
+            && self.schedule.matches_always(seconds)
+    }
+
+    /// Lazily iterate over the fire times after `start`
+    pub fn iter_from(&self, start: DateTime<Utc>) -> FireTimes {
+        FireTimes::new(self.clone(), start, None)
+    }
 
-            let unit = match index {
-                0 => Unit::Minute,
-                1 => Unit::Hour,
-                2 => Unit::Dom,
-                3 => Unit::Month,
-                4 => Unit::Dow,
-                _ => unreachable!(),
-            };
-
-            // replacing .flat_map mainly to properly bubble the error
-            let mut range = Vec::new();
-            for part in field.split(',') {
-                let r = unit.range_from_str(part)?;
-                range.extend(r);
+    /// Fire times from `start` (inclusive) up to `end` (exclusive)
+    pub fn between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> FireTimes {
+        FireTimes::new(self.clone(), start - Duration::nanoseconds(1), Some(end))
+    }
+
+    pub fn count_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> usize {
+        self.between(start, end).count()
+    }
+}
+
+// Length of a DST gap in the day before `time`, zero without one
+fn gap(tz: Tz, time: DateTime<Utc>) -> Duration {
+    let offset = |t: DateTime<Utc>| t.with_timezone(&tz).offset().fix().local_minus_utc();
+    let before = time.checked_sub_signed(Duration::days(1)).map_or(0, offset);
+    Duration::seconds((offset(time) - before).max(0) as _)
+}
+
+impl Expression {
+    #[cfg(feature = "python")]
+    /// Naive datetimes are wall-clock times in the expression's timezone,
+    /// returns whether the original was timezone-aware
+    pub(super) fn to_utc(&self, now: Moment) -> (DateTime<Utc>, bool) {
+        match (now, self.timezone) {
+            (Moment::Aware(now), _) => (now.with_timezone(&Utc), true),
+            (Moment::Naive(now), Some(tz)) => (localize(tz, now).with_timezone(&Utc), false),
+            (Moment::Naive(now), None) => (Utc.from_utc_datetime(&now), false),
+        }
+    }
+
+    #[cfg(feature = "python")]
+    /// Converts back to the expression's timezone, naive if the input was naive
+    pub(super) fn to_local(&self, py: Python<'_>, time: DateTime<Utc>, aware: bool) -> PyObject {
+        let offset = self
+            .timezone
+            .map_or(Utc.fix(), |tz| time.with_timezone(&tz).offset().fix());
+        let time = time.with_timezone(&offset);
+
+        if aware {
+            time.into_py(py)
+        } else {
+            time.naive_local().into_py(py)
+        }
+    }
+
+    #[cfg(feature = "python")]
+    fn evaluate(
+        &self,
+        py: Python<'_>,
+        now: Moment,
+        f: fn(&Self, DateTime<Utc>) -> Option<DateTime<Utc>>,
+    ) -> Option<PyObject> {
+        let (now, aware) = self.to_utc(now);
+        f(self, now).map(|time| self.to_local(py, time, aware))
+    }
+
+    // DST policy of `next_utc`, without the calendar
+    fn next_any_day(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz = match self.timezone {
+            Some(tz) if self.interval.is_none() => tz,
+            _ => return Some(Utc.from_utc_datetime(&self.next(now.naive_utc())?)),
+        };
+
+        // wall-clock times skipped by a gap fire shifted after it, maybe after `now`
+        let local = local_time(tz, now);
+        let mut local = local.checked_sub_signed(gap(tz, now)).unwrap_or(local);
+        loop {
+            local = self.next(local)?;
+            let next = localize(tz, local);
+            // already fired on the first occurrence of a repeated time
+            if next > now {
+                return Some(next.with_timezone(&Utc));
             }
+        }
+    }
 
-            schedule.insert(unit, range.into_iter().sorted().collect_vec());
+    fn prev_any_day(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz = match self.timezone {
+            Some(tz) if self.interval.is_none() => tz,
+            _ => return Some(Utc.from_utc_datetime(&self.prev(now.naive_utc())?)),
+        };
+
+        let mut local = local_time(tz, now);
+        loop {
+            local = self.prev(local)?;
+            let prev = localize(tz, local);
+            // shifted out of a DST gap to a time that hasn't happened yet
+            if prev < now {
+                return Some(prev.with_timezone(&Utc));
+            }
         }
+    }
+
+    fn matches_any_day(&self, time: DateTime<Utc>) -> bool {
+        let Some(tz) = self.timezone else {
+            return self.matches(time.naive_utc());
+        };
 
-        Ok(adjust_days_to_month(schedule, year, month))
+        let local = local_time(tz, time);
+        // skipped wall-clock times fire shifted by the length of the gap
+        [local, local - gap(tz, time)]
+            .into_iter()
+            .any(|wall| self.matches(wall) && localize(tz, wall) == time)
+    }
+
+    fn tz(&self) -> Tz {
+        self.timezone.unwrap_or(Tz::UTC)
+    }
+
+    // seconds from UTC to the wall-clock time at `time`
+    fn offset(&self, time: DateTime<Utc>) -> i32 {
+        time.with_timezone(&self.tz())
+            .offset()
+            .fix()
+            .local_minus_utc()
+    }
+
+    // Expressions that already fired are valid, search from the first supported year
+    fn validate(&self) -> Result<(), CronError> {
+        let start = DateTime::UNIX_EPOCH.naive_utc() - Duration::seconds(1);
+        self.schedule
+            .next_after(start)
+            .ok_or(CronError::NeverFires)?;
+        Ok(())
     }
 }
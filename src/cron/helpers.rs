@@ -1,77 +1,217 @@
-use super::unit::Unit;
-use chrono::{Datelike, NaiveDate};
-use itertools::Itertools;
-use std::collections::HashMap;
+use super::calendar::HolidayPolicy;
+use super::error::CronError;
+use super::schedule::DaySemantics;
+use super::unit::Modifier;
+use anyhow::{anyhow, Result};
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::Tz;
 
-pub type Schedule = HashMap<Unit, Vec<i32>>;
+/// Expands nicknames like `@daily` into their equivalent five fields
+pub fn expand_macro(expression: &str) -> Result<&str, CronError> {
+    if !expression.trim().starts_with('@') {
+        return Ok(expression);
+    }
+    let expression = expression.trim();
 
-pub fn get_next(values: &[i32], unit: i32) -> (i32, bool) {
-    if let Some(v) = values.iter().find(|&&v| v > unit) {
-        return (*v, false);
+    match expression.to_lowercase().as_str() {
+        "@yearly" | "@annually" => Ok("0 0 1 1 *"),
+        "@monthly" => Ok("0 0 1 * *"),
+        "@weekly" => Ok("0 0 * * 0"),
+        "@daily" | "@midnight" => Ok("0 0 * * *"),
+        "@hourly" => Ok("0 * * * *"),
+        _ => Err(CronError::UnknownMacro(expression.into())),
     }
+}
+
+/// Converts a one-shot `at:` datetime, like `2026-12-31T23:00`, into a 7-field expression
+pub fn parse_at(at: &str) -> Result<String, CronError> {
+    let time = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(at, format).ok())
+    .filter(|time| (1970..=2099).contains(&time.year()))
+    .ok_or_else(|| CronError::InvalidDateTime(at.into()))?;
+
+    Ok(format!(
+        "{} {} {} {} {} * {}",
+        time.second(),
+        time.minute(),
+        time.hour(),
+        time.day(),
+        time.month(),
+        time.year()
+    ))
+}
 
-    return (*values.first().unwrap(), true);
+/// Whitespace-separated fields, with the character offset each one starts at
+pub fn split_fields(expression: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+    expression
+        .split(char::is_whitespace)
+        .filter_map(|field| {
+            let start = offset;
+            offset += field.chars().count() + 1;
+            (!field.is_empty()).then_some((start, field))
+        })
+        .collect()
 }
 
-pub fn next_month(time: HashMap<Unit, i32>) -> HashMap<Unit, i32> {
-    let mut time = time;
-    if time[&Unit::Month] == 12 {
-        time.entry(Unit::Year).and_modify(|v| *v += 1);
-        time = Unit::Month.set(time, 0);
+/// Parses Go-style durations used by `@every`, like `90m`, `1h30m` or `45s`
+pub fn parse_interval(interval: &str) -> Result<Duration, CronError> {
+    let invalid = |reason| CronError::InvalidInterval {
+        interval: interval.into(),
+        reason,
+    };
+
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+
+    for c in interval.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let value: i64 = digits.parse().map_err(|_| invalid("is not valid"))?;
+        digits.clear();
+
+        let value = match c {
+            'h' => Duration::try_hours(value),
+            'm' => Duration::try_minutes(value),
+            's' => Duration::try_seconds(value),
+            _ => return Err(invalid("has an unknown unit, use h, m or s")),
+        };
+        total = value
+            .and_then(|value| total.checked_add(&value))
+            .ok_or_else(|| invalid("is too long"))?;
+    }
+
+    if !digits.is_empty() {
+        return Err(invalid("is missing a unit"));
+    }
+
+    if total < Duration::seconds(1) {
+        return Err(invalid("must be at least one second"));
     }
-    time.entry(Unit::Month).and_modify(|v| *v += 1);
-    time = Unit::Day.set(time, 1);
-    time = Unit::Hour.set(time, 0);
-    // Minutes are either reset or incremented to next value (0)
-    time = Unit::Minute.set(time, -1);
-    time
+
+    Ok(total)
+}
+
+/// Stable FNV-1a hash of the name `H` spreads schedules by, like a graph name
+pub fn seed(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
-pub fn adjust_days_to_month(mut schedule: Schedule, year: i32, month: i32) -> Schedule {
-    // Month is not in schedule and therefore has no days
-    if !schedule[&Unit::Month].contains(&(month as _)) {
-        return schedule;
+pub fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone
+        .parse()
+        .map_err(|_| anyhow!("Unknown timezone {}", timezone))
+}
+
+pub fn parse_day_semantics(day_semantics: &str) -> Result<DaySemantics> {
+    match day_semantics.to_lowercase().as_str() {
+        "or" => Ok(DaySemantics::Or),
+        "and" => Ok(DaySemantics::And),
+        _ => Err(anyhow!(
+            "Unknown day semantics {}, use \"or\" or \"and\"",
+            day_semantics
+        )),
     }
+}
+
+pub fn parse_holiday_policy(policy: &str) -> Result<HolidayPolicy> {
+    match policy.to_lowercase().as_str() {
+        "skip" => Ok(HolidayPolicy::Skip),
+        "shift" => Ok(HolidayPolicy::Shift),
+        _ => Err(anyhow!(
+            "Unknown holiday policy {}, use \"skip\" or \"shift\"",
+            policy
+        )),
+    }
+}
+
+/// Wall-clock time of `time` in `tz`, clamped to the dates chrono can represent
+/// so that searches from the ends of time don't overflow
+pub fn local_time(tz: Tz, time: DateTime<Utc>) -> NaiveDateTime {
+    let offset = tz.offset_from_utc_datetime(&time.naive_utc()).fix();
+    let offset = Duration::seconds(offset.local_minus_utc() as _);
+    match time.naive_utc().checked_add_signed(offset) {
+        Some(local) => local,
+        None if offset > Duration::zero() => NaiveDateTime::MAX,
+        None => NaiveDateTime::MIN,
+    }
+}
+
+/// Resolves a wall-clock time in `tz`. Repeated times map to their first occurrence,
+/// skipped times are read with the offset in effect before the gap
+pub fn localize(tz: Tz, time: NaiveDateTime) -> DateTime<Tz> {
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(t) => t,
+        LocalResult::Ambiguous(first, _) => first,
+        LocalResult::None => {
+            let before = time.checked_sub_signed(Duration::days(1)).unwrap_or(time);
+            let offset = tz.offset_from_utc_datetime(&before).fix().local_minus_utc();
+            // wall-clock times at the ends of time may have no UTC time either
+            let utc = match time.checked_sub_signed(Duration::seconds(offset as _)) {
+                Some(utc) => utc,
+                None if offset < 0 => NaiveDateTime::MAX,
+                None => NaiveDateTime::MIN,
+            };
+            tz.from_utc_datetime(&utc)
+        }
+    }
+}
+
+pub fn resolve_modifier(modifier: &Modifier, year: i32, month: i32, max_days: i32) -> Option<i32> {
+    // day of week with Sunday = 0, same as the Dow field
+    let weekday = |day: i32| {
+        NaiveDate::from_ymd_opt(year, month as _, day as _)
+            .unwrap()
+            .weekday()
+            .num_days_from_sunday() as i32
+    };
+
+    let day = match *modifier {
+        Modifier::LastDay(offset) => max_days - offset,
+        Modifier::LastWeekday => match weekday(max_days) {
+            6 => max_days - 1,
+            0 => max_days - 2,
+            _ => max_days,
+        },
+        Modifier::NearestWeekday(day) => {
+            if day > max_days {
+                return None;
+            }
+            // never jump over the month boundaries
+            match weekday(day) {
+                6 if day == 1 => day + 2,
+                6 => day - 1,
+                0 if day == max_days => day - 2,
+                0 => day + 1,
+                _ => day,
+            }
+        }
+        Modifier::LastDow(dow) => max_days - (weekday(max_days) - dow).rem_euclid(7),
+        Modifier::NthDow(dow, nth) => 1 + (dow - weekday(1)).rem_euclid(7) + 7 * (nth - 1),
+    };
 
-    let max_days = days_in_month(year, month as _);
-
-    let first_dow = NaiveDate::from_ymd_opt(year, month as _, 1)
-        .unwrap()
-        .weekday()
-        .num_days_from_monday() as i32;
-
-    let first_appearances = schedule
-        .get(&Unit::Dow)
-        .unwrap_or(&vec![])
-        .iter()
-        .map(|day| (day - first_dow) % 7)
-        .collect_vec();
-
-    let dow_to_dom = first_appearances
-        .iter()
-        .flat_map(|day| (0..5).map(|i| day + 7 * i).collect_vec())
-        .collect_vec();
-
-    let days = schedule
-        .get(&Unit::Dom)
-        .unwrap_or(&vec![])
-        .clone()
-        .into_iter()
-        .chain(dow_to_dom)
-        .filter(|&day| 0 < day && day <= max_days)
-        .sorted()
-        .dedup()
-        .collect();
-
-    schedule.insert(Unit::Day, days);
-    schedule
+    (0 < day && day <= max_days).then_some(day)
 }
 
 fn is_leap_year(year: i32) -> bool {
     (year % 4 == 0) && (year % 100 != 0 || year % 400 == 0)
 }
 
-fn days_in_month(year: i32, month: i32) -> i32 {
+pub fn days_in_month(year: i32, month: i32) -> i32 {
     match month {
         2 => {
             if is_leap_year(year) {
@@ -0,0 +1,64 @@
+use super::expression::Expression;
+use chrono::{DateTime, Utc};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// Lazily walks the fire times of an expression
+#[cfg_attr(feature = "python", pyclass)]
+pub struct FireTimes {
+    expression: Expression,
+    current: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    // python callers get naive datetimes back if they passed naive ones
+    aware: bool,
+}
+
+impl FireTimes {
+    pub fn new(expression: Expression, start: DateTime<Utc>, end: Option<DateTime<Utc>>) -> Self {
+        FireTimes {
+            expression,
+            current: start,
+            end,
+            aware: true,
+        }
+    }
+
+    pub fn aware(mut self, aware: bool) -> Self {
+        self.aware = aware;
+        self
+    }
+}
+
+impl Iterator for FireTimes {
+    type Item = DateTime<Utc>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // stops once a schedule with a year field or an `at:` is exhausted
+        let next = self.expression.next_utc(self.current)?;
+
+        if self.end.is_some_and(|end| next >= end) {
+            return None;
+        }
+
+        self.current = next;
+        Some(next)
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl FireTimes {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> Option<PyObject> {
+        let next = Iterator::next(self)?;
+        Some(self.expression.to_local(py, next, self.aware))
+    }
+
+    /// The next `n` fire times, fewer if the iterator or the schedule ends
+    fn take(&mut self, py: Python<'_>, n: usize) -> Vec<PyObject> {
+        (0..n).map_while(|_| self.__next__(py)).collect()
+    }
+}
@@ -1,6 +1,17 @@
+pub mod calendar;
+pub mod composite;
+pub mod crontab;
+mod describe;
+pub mod error;
 pub mod expression;
 mod helpers;
+pub mod iter;
+pub mod schedule;
+pub mod timetable;
+mod token;
 mod unit;
 
+#[cfg(test)]
+mod reference;
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,286 @@
+//! Brute-force reference for `Expression::next` and `Expression::prev`, and proptest
+//! strategies for the 5-field expressions it understands. Fields are generated as
+//! parts, rendered to cron syntax for the parser and matched directly by the reference
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use proptest::prelude::*;
+use std::fmt;
+
+/// The reference gives up after this many days, long enough for leap days
+/// and for every day of the month to fall on every day of the week
+pub const HORIZON_DAYS: i64 = 8 * 366;
+
+/// A comma-separated part of a field
+#[derive(Clone, Debug)]
+pub enum Part {
+    /// `*` or `*/step`
+    Star(Option<i32>),
+    /// `start`, `start/step`, `start-end` or `start-end/step`, wrapping around if `end < start`
+    Range(i32, Option<i32>, Option<i32>),
+    /// `L` or `L-offset`
+    LastDay(i32),
+    /// `LW`
+    LastWeekday,
+    /// `dayW`
+    NearestWeekday(i32),
+    /// `dowL`
+    LastDow(i32),
+    /// `dow#nth`
+    NthDow(i32, i32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub parts: Vec<Part>,
+    // render months and days of week as names
+    names: bool,
+}
+
+/// Minute, hour, day of month, month and day of week
+#[derive(Clone, Debug)]
+pub struct Fields {
+    pub fields: [Field; 5],
+    pub and: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const DOWS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+impl Field {
+    fn render(&self, names: Option<&[&str]>, first: i32) -> String {
+        let value = |value: i32| match (self.names, names) {
+            (true, Some(names)) if value - first < names.len() as i32 => {
+                names[(value - first) as usize].to_string()
+            }
+            _ => value.to_string(),
+        };
+        let step = |step: Option<i32>| step.map(|step| format!("/{}", step)).unwrap_or_default();
+
+        let parts: Vec<String> = self
+            .parts
+            .iter()
+            .map(|part| match *part {
+                Part::Star(by) => format!("*{}", step(by)),
+                Part::Range(start, None, by) => format!("{}{}", value(start), step(by)),
+                Part::Range(start, Some(end), by) => {
+                    format!("{}-{}{}", value(start), value(end), step(by))
+                }
+                Part::LastDay(0) => "L".into(),
+                Part::LastDay(offset) => format!("L-{}", offset),
+                Part::LastWeekday => "LW".into(),
+                Part::NearestWeekday(day) => format!("{}W", day),
+                Part::LastDow(dow) => format!("{}L", dow),
+                Part::NthDow(dow, nth) => format!("{}#{}", dow, nth),
+            })
+            .collect();
+        parts.join(",")
+    }
+
+    fn is_star(&self) -> bool {
+        matches!(self.parts.as_slice(), [Part::Star(None)])
+    }
+
+    // Values of the plain parts between `min` and `max`, the modifiers are resolved
+    // per day. Ranges wrap around after `wrap`, which is Saturday for days of week
+    fn values(&self, (min, max): (i32, i32), wrap: i32) -> Vec<i32> {
+        let mut values = vec![];
+        for part in self.parts.iter() {
+            let (start, end, by) = match *part {
+                Part::Star(by) => (min, max, by),
+                Part::Range(start, None, None) => (start, start, None),
+                Part::Range(start, None, by) => (start, max, by),
+                Part::Range(start, Some(end), by) => (start, end, by),
+                _ => continue,
+            };
+            // Sunday 7 at the start of a range is followed by Monday
+            let (start, end) = match (wrap < max, start == max, end == max) {
+                (true, true, true) => (min, min),
+                (true, true, false) => (min, end),
+                _ => (start, end),
+            };
+            let range: Vec<i32> = match start <= end {
+                true => (start..=end).collect(),
+                false => (start..=wrap).chain(min..=end).collect(),
+            };
+            values.extend(range.into_iter().step_by(by.unwrap_or(1) as usize));
+        }
+        values
+    }
+}
+
+impl fmt::Display for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [minute, hour, dom, month, dow] = &self.fields;
+        write!(
+            f,
+            "{} {} {} {} {}",
+            minute.render(None, 0),
+            hour.render(None, 0),
+            dom.render(None, 1),
+            month.render(Some(&MONTHS), 1),
+            dow.render(Some(&DOWS), 0)
+        )
+    }
+}
+
+impl Fields {
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        let [_, _, dom, month, dow] = &self.fields;
+        if !month.values((1, 12), 12).contains(&(date.month() as i32)) {
+            return false;
+        }
+
+        let doms = matches_dom(dom, date);
+        let dows = matches_dow(dow, date);
+        match (self.and, dom.is_star(), dow.is_star()) {
+            (true, _, _) => doms && dows,
+            (false, true, false) => dows,
+            (false, false, true) => doms,
+            (false, _, _) => doms || dows,
+        }
+    }
+
+    // Every minute of the matching days in `days`, in order
+    fn minutes<'a>(
+        &'a self,
+        days: impl Iterator<Item = NaiveDate> + 'a,
+        rev: bool,
+    ) -> impl Iterator<Item = NaiveDateTime> + 'a {
+        let [minute, hour, ..] = &self.fields;
+        let (minutes, hours) = (minute.values((0, 59), 59), hour.values((0, 23), 23));
+
+        days.filter(|date| self.matches_date(*date))
+            .flat_map(move |date| {
+                let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+                let day = (0..24 * 60).map(move |m| midnight + Duration::minutes(m));
+                let day: Box<dyn Iterator<Item = NaiveDateTime>> = match rev {
+                    true => Box::new(day.rev()),
+                    false => Box::new(day),
+                };
+                day
+            })
+            .filter(move |time| {
+                hours.contains(&(time.hour() as i32)) && minutes.contains(&(time.minute() as i32))
+            })
+    }
+
+    /// First match strictly after `now`, checking every minute of the matching days
+    pub fn next(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let days = (0..HORIZON_DAYS).map(|day| now.date() + Duration::days(day));
+        self.minutes(days, false).find(|time| *time > now)
+    }
+
+    /// Last match strictly before `now`, mirrors `next`
+    pub fn prev(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let days = (0..HORIZON_DAYS).map(|day| now.date() - Duration::days(day));
+        self.minutes(days, true).find(|time| *time < now)
+    }
+}
+
+fn last_day(date: NaiveDate) -> u32 {
+    (28..=31)
+        .rev()
+        .find(|day| date.with_day(*day).is_some())
+        .unwrap()
+}
+
+fn is_weekday(date: NaiveDate) -> bool {
+    date.weekday().num_days_from_monday() < 5
+}
+
+fn matches_dom(field: &Field, date: NaiveDate) -> bool {
+    let (day, last) = (date.day() as i32, last_day(date) as i32);
+    let weekdays = || (1..=last).filter(|d| is_weekday(date.with_day(*d as u32).unwrap()));
+
+    field.values((1, 31), 31).contains(&day)
+        || field.parts.iter().any(|part| match *part {
+            Part::LastDay(offset) => day == last - offset,
+            Part::LastWeekday => weekdays().next_back() == Some(day),
+            // the closest weekday in the month, never the same distance on both sides
+            Part::NearestWeekday(target) => {
+                target <= last && weekdays().min_by_key(|d| (d - target).abs()) == Some(day)
+            }
+            _ => false,
+        })
+}
+
+fn matches_dow(field: &Field, date: NaiveDate) -> bool {
+    let (day, last) = (date.day() as i32, last_day(date) as i32);
+    let dow = date.weekday().num_days_from_sunday() as i32;
+
+    let dows = field.values((0, 7), 6);
+    dows.iter().any(|d| d % 7 == dow)
+        || field.parts.iter().any(|part| match *part {
+            Part::LastDow(d) => d % 7 == dow && day + 7 > last,
+            Part::NthDow(d, nth) => d % 7 == dow && (day - 1) / 7 + 1 == nth,
+            _ => false,
+        })
+}
+
+fn step() -> impl Strategy<Value = Option<i32>> {
+    prop::option::weighted(0.3, 1..=12)
+}
+
+fn plain((min, max): (i32, i32)) -> impl Strategy<Value = Part> {
+    prop_oneof![
+        (min..=max, step()).prop_map(|(start, by)| Part::Range(start, None, by)),
+        (min..=max, min..=max, step()).prop_map(|(start, end, by)| Part::Range(
+            start,
+            Some(end),
+            by
+        )),
+    ]
+}
+
+fn field(part: BoxedStrategy<Part>) -> impl Strategy<Value = Field> {
+    let parts = prop_oneof![
+        1 => step().prop_map(|by| vec![Part::Star(by)]),
+        3 => prop::collection::vec(part, 1..=3),
+    ];
+    (parts, any::<bool>()).prop_map(|(parts, names)| Field { parts, names })
+}
+
+fn dom_part() -> BoxedStrategy<Part> {
+    prop_oneof![
+        4 => plain((1, 31)),
+        1 => (0..=30).prop_map(Part::LastDay),
+        1 => Just(Part::LastWeekday),
+        1 => (1..=31).prop_map(Part::NearestWeekday),
+    ]
+    .boxed()
+}
+
+fn dow_part() -> BoxedStrategy<Part> {
+    prop_oneof![
+        4 => plain((0, 7)),
+        1 => (0..=7).prop_map(Part::LastDow),
+        1 => (0..=7, 1..=5).prop_map(|(dow, nth)| Part::NthDow(dow, nth)),
+    ]
+    .boxed()
+}
+
+/// Valid 5-field expressions, some of which may never fire
+pub fn expressions() -> impl Strategy<Value = Fields> {
+    (
+        field(plain((0, 59)).boxed()),
+        field(plain((0, 23)).boxed()),
+        field(dom_part()),
+        field(plain((1, 12)).boxed()),
+        field(dow_part()),
+        any::<bool>(),
+    )
+        .prop_map(|(minute, hour, dom, month, dow, and)| Fields {
+            fields: [minute, hour, dom, month, dow],
+            and,
+        })
+}
+
+/// Times between 1979 and 2090, so the horizon stays within the supported years both ways
+pub fn times() -> impl Strategy<Value = NaiveDateTime> {
+    let start = NaiveDate::from_ymd_opt(1979, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    (0..111 * 365 * 86400i64).prop_map(move |seconds| start + Duration::seconds(seconds))
+}
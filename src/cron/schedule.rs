@@ -0,0 +1,440 @@
+use super::error::CronError;
+use super::helpers::{days_in_month, resolve_modifier};
+use super::unit::{Modifier, Part, Unit};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+// An expression that can't fire in this many years contains an invalid date,
+// like Feb-30. Weekdays of a date repeat every 28 years, except around
+// centuries that aren't leap years, so a Monday Feb-29 can be 40 years apart
+const MAX_YEARS: i32 = 50;
+
+/// Bits of every value of a field
+pub const ALL_SECONDS: u64 = (1 << 60) - 1;
+const ALL_MINUTES: u64 = ALL_SECONDS;
+const ALL_HOURS: u64 = (1 << 24) - 1;
+const ALL_DAYS: u64 = (1 << 32) - 2;
+const ALL_MONTHS: u64 = (1 << 13) - 2;
+const ALL_DOWS: u64 = (1 << 7) - 1;
+
+/// How day-of-month and day-of-week combine when both are restricted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DaySemantics {
+    /// Classic cron, a day matches if either field matches
+    #[default]
+    Or,
+    /// A day matches if both fields match, like Friday the 13th as `0 0 13 * fri`
+    And,
+}
+
+/// Allowed values of every field as bitmasks, bit `n` is set if value `n` is allowed.
+/// Precomputed once when parsing so evaluating a time is only bit operations
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    seconds: u64,
+    minutes: u64,
+    hours: u64,
+    doms: u64,
+    months: u64,
+    dows: u64,
+    // sorted, None if every year is allowed
+    years: Option<Vec<i32>>,
+    // month-dependent days, resolved in `days_of_month`
+    modifiers: Vec<Modifier>,
+    // an unrestricted dom or dow field is ignored if the other one is restricted
+    ignore_dom: bool,
+    ignore_dow: bool,
+    day_semantics: DaySemantics,
+}
+
+impl Schedule {
+    /// `offsets` are where each field starts in the expression, to locate errors,
+    /// `seed` picks the values of `H`
+    pub fn new(fields: &[String; 7], offsets: [usize; 7], seed: u64) -> Result<Self, CronError> {
+        let [second, minute, hour, dom, month, dow, year] = fields;
+        let [o_second, o_minute, o_hour, o_dom, o_month, o_dow, o_year] = offsets;
+
+        let mut schedule = Schedule {
+            ignore_dom: dom == "*" && dow != "*",
+            ignore_dow: dow == "*" && dom != "*",
+            ..Default::default()
+        };
+
+        schedule.seconds = schedule.parse_field(Unit::Second, second, o_second, seed)?;
+        schedule.minutes = schedule.parse_field(Unit::Minute, minute, o_minute, seed)?;
+        schedule.hours = schedule.parse_field(Unit::Hour, hour, o_hour, seed)?;
+        schedule.doms = schedule.parse_field(Unit::Dom, dom, o_dom, seed)?;
+        schedule.months = schedule.parse_field(Unit::Month, month, o_month, seed)?;
+        schedule.dows = schedule.parse_field(Unit::Dow, dow, o_dow, seed)?;
+        if year != "*" {
+            let mut years = schedule.parse_values(Unit::Year, year, o_year, seed)?;
+            years.sort();
+            years.dedup();
+            schedule.years = Some(years);
+        }
+
+        Ok(schedule)
+    }
+
+    pub fn with_day_semantics(mut self, day_semantics: DaySemantics) -> Self {
+        self.day_semantics = day_semantics;
+        self
+    }
+
+    pub fn day_semantics(&self) -> DaySemantics {
+        self.day_semantics
+    }
+
+    pub fn seconds(&self) -> u64 {
+        self.seconds
+    }
+
+    /// First fire time strictly after `now`, None if there is none in the next years
+    pub fn next_after(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        // fire times have second precision, start from the next whole second
+        let start = now
+            .with_nanosecond(0)?
+            .checked_add_signed(Duration::seconds(1))?;
+
+        let (mut year, mut month, mut day) =
+            (start.year(), start.month() as i32, start.day() as i32);
+        let (mut hour, mut minute, mut second) = (
+            start.hour() as i32,
+            start.minute() as i32,
+            start.second() as i32,
+        );
+
+        // restricted years are searched until the last one
+        let limit = match &self.years {
+            Some(years) => years.last().copied().unwrap_or(year),
+            None => year + MAX_YEARS,
+        }
+        .min(NaiveDate::MAX.year());
+        let mut days = None;
+
+        // Find the first allowed value of each field, starting from the highest one.
+        // If a field has none left, move the field above it and reset the lower fields
+        while year <= limit {
+            let next = self.next_year(year)?;
+            if next != year {
+                (year, month, day, hour, minute, second) = (next, 1, 1, 0, 0, 0);
+            }
+
+            let Some(next) = next_bit(self.months, month) else {
+                (year, month, day, hour, minute, second) = (year + 1, 1, 1, 0, 0, 0);
+                continue;
+            };
+            if next != month {
+                (month, day, hour, minute, second) = (next, 1, 0, 0, 0);
+            }
+
+            let mask = match days {
+                Some((y, m, mask)) if y == year && m == month => mask,
+                _ => self.days_of_month(year, month),
+            };
+            days = Some((year, month, mask));
+
+            let Some(next) = next_bit(mask, day) else {
+                (month, day, hour, minute, second) = (month + 1, 1, 0, 0, 0);
+                continue;
+            };
+            if next != day {
+                (day, hour, minute, second) = (next, 0, 0, 0);
+            }
+
+            let Some(next) = next_bit(self.hours, hour) else {
+                (day, hour, minute, second) = (day + 1, 0, 0, 0);
+                continue;
+            };
+            if next != hour {
+                (hour, minute, second) = (next, 0, 0);
+            }
+
+            let Some(next) = next_bit(self.minutes, minute) else {
+                (hour, minute, second) = (hour + 1, 0, 0);
+                continue;
+            };
+            if next != minute {
+                (minute, second) = (next, 0);
+            }
+
+            let Some(next) = next_bit(self.seconds, second) else {
+                (minute, second) = (minute + 1, 0);
+                continue;
+            };
+
+            return to_datetime(year, month, day, hour, minute, next);
+        }
+
+        None
+    }
+
+    /// Last fire time strictly before `now`, mirrors `next_after`
+    pub fn prev_before(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        // a match earlier within the same second is still before `now`
+        let start = match now.with_nanosecond(0)? {
+            truncated if truncated < now => truncated,
+            truncated => truncated.checked_sub_signed(Duration::seconds(1))?,
+        };
+
+        let (mut year, mut month, mut day) =
+            (start.year(), start.month() as i32, start.day() as i32);
+        let (mut hour, mut minute, mut second) = (
+            start.hour() as i32,
+            start.minute() as i32,
+            start.second() as i32,
+        );
+
+        let limit = match &self.years {
+            Some(years) => years.first().copied().unwrap_or(year),
+            None => year - MAX_YEARS,
+        }
+        .max(NaiveDate::MIN.year());
+        let mut days = None;
+
+        while year >= limit {
+            let prev = self.prev_year(year)?;
+            if prev != year {
+                (year, month, day, hour, minute, second) = (prev, 12, 31, 23, 59, 59);
+            }
+
+            let Some(prev) = prev_bit(self.months, month) else {
+                (year, month, day, hour, minute, second) = (year - 1, 12, 31, 23, 59, 59);
+                continue;
+            };
+            if prev != month {
+                (month, day, hour, minute, second) = (prev, 31, 23, 59, 59);
+            }
+
+            let mask = match days {
+                Some((y, m, mask)) if y == year && m == month => mask,
+                _ => self.days_of_month(year, month),
+            };
+            days = Some((year, month, mask));
+
+            let Some(prev) = prev_bit(mask, day) else {
+                (month, day, hour, minute, second) = (month - 1, 31, 23, 59, 59);
+                continue;
+            };
+            if prev != day {
+                (day, hour, minute, second) = (prev, 23, 59, 59);
+            }
+
+            let Some(prev) = prev_bit(self.hours, hour) else {
+                (day, hour, minute, second) = (day - 1, 23, 59, 59);
+                continue;
+            };
+            if prev != hour {
+                (hour, minute, second) = (prev, 59, 59);
+            }
+
+            let Some(prev) = prev_bit(self.minutes, minute) else {
+                (hour, minute, second) = (hour - 1, 59, 59);
+                continue;
+            };
+            if prev != minute {
+                (minute, second) = (prev, 59);
+            }
+
+            let Some(prev) = prev_bit(self.seconds, second) else {
+                (minute, second) = (minute - 1, 59);
+                continue;
+            };
+
+            return to_datetime(year, month, day, hour, minute, prev);
+        }
+
+        None
+    }
+
+    /// First time after `now`, a match, that may not match. Only the seconds in the
+    /// `seconds` bitmask are checked, like the seconds another schedule fires on.
+    /// Days aren't checked, so the result is the next midnight at the latest, None
+    /// past the last date chrono can represent
+    pub fn next_miss(&self, now: NaiveDateTime, seconds: u64) -> Option<NaiveDateTime> {
+        let minute = now.with_nanosecond(0)?.with_second(0)?;
+        let hour = minute.with_minute(0)?;
+        let day = hour.with_hour(0)?;
+
+        let missed = seconds & !self.seconds;
+        if missed != 0 {
+            return match next_bit(missed, now.second() as i32 + 1) {
+                Some(second) => minute.checked_add_signed(Duration::seconds(second as _)),
+                None => minute.checked_add_signed(Duration::minutes(1)),
+            };
+        }
+        let missed = ALL_MINUTES & !self.minutes;
+        if missed != 0 {
+            return match next_bit(missed, now.minute() as i32 + 1) {
+                Some(minute) => hour.checked_add_signed(Duration::minutes(minute as _)),
+                None => hour.checked_add_signed(Duration::hours(1)),
+            };
+        }
+        match next_bit(ALL_HOURS & !self.hours, now.hour() as i32 + 1) {
+            Some(hour) => day.checked_add_signed(Duration::hours(hour as _)),
+            None => day.checked_add_signed(Duration::days(1)),
+        }
+    }
+
+    /// Whether every time whose second is in `seconds` matches
+    pub fn matches_always(&self, seconds: u64) -> bool {
+        let doms = self.doms & ALL_DAYS == ALL_DAYS;
+        let dows = self.dows & ALL_DOWS == ALL_DOWS;
+        // modifiers only add days
+        let days = match self.day_semantics {
+            DaySemantics::And => doms && dows,
+            DaySemantics::Or if self.ignore_dom => dows,
+            DaySemantics::Or if self.ignore_dow => doms,
+            DaySemantics::Or => doms || dows,
+        };
+
+        seconds & !self.seconds == 0
+            && self.minutes == ALL_MINUTES
+            && self.hours == ALL_HOURS
+            && self.months == ALL_MONTHS
+            && self.years.is_none()
+            && days
+    }
+
+    pub fn matches(&self, time: NaiveDateTime) -> bool {
+        let has = |mask: u64, value: u32| mask & (1 << value) != 0;
+
+        time.nanosecond() == 0
+            && self.next_year(time.year()) == Some(time.year())
+            && has(self.seconds, time.second())
+            && has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month())
+            && has(
+                self.days_of_month(time.year(), time.month() as _),
+                time.day(),
+            )
+    }
+
+    /// Days of the given month allowed by the dom, dow and modifiers
+    fn days_of_month(&self, year: i32, month: i32) -> u64 {
+        let max_days = days_in_month(year, month);
+        // bits 1 through max_days
+        let in_month = (1 << (max_days + 1)) - 2;
+
+        let mut doms = self.doms;
+        let mut dows = 0;
+
+        // day of week of the 1st with Sunday = 0, same as the Dow field
+        let first_dow = NaiveDate::from_ymd_opt(year, month as _, 1)
+            .unwrap()
+            .weekday()
+            .num_days_from_sunday() as i32;
+
+        for day in 1..=max_days {
+            if self.dows & (1 << ((first_dow + day - 1) % 7)) != 0 {
+                dows |= 1 << day;
+            }
+        }
+
+        for modifier in self.modifiers.iter() {
+            let Some(day) = resolve_modifier(modifier, year, month, max_days) else {
+                continue;
+            };
+            match modifier {
+                Modifier::LastDow(_) | Modifier::NthDow(..) => dows |= 1 << day,
+                _ => doms |= 1 << day,
+            }
+        }
+
+        let days = match self.day_semantics {
+            DaySemantics::And => doms & dows,
+            DaySemantics::Or if self.ignore_dom => dows,
+            DaySemantics::Or if self.ignore_dow => doms,
+            DaySemantics::Or => doms | dows,
+        };
+
+        days & in_month
+    }
+
+    fn parse_field(
+        &mut self,
+        unit: Unit,
+        field: &str,
+        offset: usize,
+        seed: u64,
+    ) -> Result<u64, CronError> {
+        let values = self.parse_values(unit, field, offset, seed)?;
+        Ok(values.iter().fold(0, |mask, value| mask | 1 << value))
+    }
+
+    fn parse_values(
+        &mut self,
+        unit: Unit,
+        field: &str,
+        offset: usize,
+        seed: u64,
+    ) -> Result<Vec<i32>, CronError> {
+        let mut values = vec![];
+        let mut start = offset;
+        for (index, part) in field.split(',').enumerate() {
+            let locate = move |e: CronError| e.locate(index, start);
+            start += part.chars().count() + 1;
+
+            match unit.parse_part(part, seed).map_err(locate)? {
+                Part::Modifier(modifier) => self.modifiers.push(modifier),
+                Part::Values(part) => values.extend(part),
+            }
+        }
+        Ok(values)
+    }
+
+    // First allowed year that is at least `from`
+    fn next_year(&self, from: i32) -> Option<i32> {
+        match &self.years {
+            Some(years) => years.iter().find(|year| **year >= from).copied(),
+            None => Some(from),
+        }
+    }
+
+    // Last allowed year that is at most `upto`
+    fn prev_year(&self, upto: i32) -> Option<i32> {
+        match &self.years {
+            Some(years) => years.iter().rev().find(|year| **year <= upto).copied(),
+            None => Some(upto),
+        }
+    }
+}
+
+// Lowest set bit that is at least `from`
+fn next_bit(mask: u64, from: i32) -> Option<i32> {
+    if from >= 64 {
+        return None;
+    }
+
+    match mask >> from {
+        0 => None,
+        rest => Some(from + rest.trailing_zeros() as i32),
+    }
+}
+
+// Highest set bit that is at most `upto`
+fn prev_bit(mask: u64, upto: i32) -> Option<i32> {
+    if upto < 0 {
+        return None;
+    }
+
+    match mask << (63 - upto) {
+        0 => None,
+        rest => Some(upto - rest.leading_zeros() as i32),
+    }
+}
+
+fn to_datetime(
+    year: i32,
+    month: i32,
+    day: i32,
+    hour: i32,
+    minute: i32,
+    second: i32,
+) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(year, month as _, day as _)?.and_hms_opt(
+        hour as _,
+        minute as _,
+        second as _,
+    )
+}
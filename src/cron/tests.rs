@@ -1,6 +1,13 @@
+use super::calendar::Calendar;
+use super::composite::Composite;
+use super::crontab;
+use super::error::{CronError, Unit};
 use super::expression::Expression;
+use super::reference::{self, HORIZON_DAYS};
+use super::timetable::{AfterRun, Interval, LastRun, Timetable};
 use anyhow::Result;
-use chrono::NaiveDateTime;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use proptest::prelude::*;
 
 fn utc_from_str(s: &str) -> NaiveDateTime {
     NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
@@ -10,7 +17,46 @@ fn test(expression: &str, input: &str, expected: &str) -> Result<bool> {
     let expression = Expression::from_str(expression)?;
     let input = utc_from_str(input);
     let expected = utc_from_str(expected);
-    Ok(expression.next(input) == expected)
+    Ok(expression.next(input) == Some(expected))
+}
+
+fn test_prev(expression: &str, input: &str, expected: &str) -> Result<bool> {
+    let expression = Expression::from_str(expression)?;
+    let input = utc_from_str(input);
+    let expected = utc_from_str(expected);
+    Ok(expression.prev(input) == Some(expected))
+}
+
+fn test_tz(expression: &str, timezone: &str, input: &str, expected: &str) -> Result<bool> {
+    let expression = Expression::from_str(expression)?.with_timezone(timezone)?;
+    let input = Utc.from_utc_datetime(&utc_from_str(input));
+    let expected = Utc.from_utc_datetime(&utc_from_str(expected));
+    Ok(expression.next_utc(input) == Some(expected))
+}
+
+fn composite(expression: &str) -> Composite {
+    Expression::from_str(expression).unwrap().into()
+}
+
+fn composite_next(schedule: &Composite, input: &str) -> Option<NaiveDateTime> {
+    let input = Utc.from_utc_datetime(&utc_from_str(input));
+    schedule.next_utc(input).map(|next| next.naive_utc())
+}
+
+fn christmas() -> Calendar {
+    Calendar::new([
+        NaiveDate::from_ymd_opt(2024, 12, 25).unwrap(),
+        NaiveDate::from_ymd_opt(2024, 12, 26).unwrap(),
+    ])
+}
+
+fn utc(s: &str) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&utc_from_str(s))
+}
+
+fn test_and(expression: &str, input: &str, expected: &str) -> Result<bool> {
+    let expression = Expression::from_str(expression)?.with_day_semantics("and")?;
+    Ok(expression.next(utc_from_str(input)) == Some(utc_from_str(expected)))
 }
 
 #[test]
@@ -345,6 +391,630 @@ fn mix_interval_and_ranges() {
     .unwrap());
 }
 
+#[test]
+fn every_second() {
+    assert!(test("* * * * * *", "2024-01-31 23:59:59", "2024-02-01 00:00:00").unwrap());
+    assert!(test("* * * * * *", "2024-01-31 23:59:00", "2024-01-31 23:59:01").unwrap());
+}
+
+#[test]
+fn second_interval() {
+    assert!(test(
+        "*/15 * * * * *",
+        "2024-01-31 23:59:00",
+        "2024-01-31 23:59:15"
+    )
+    .unwrap());
+    assert!(test(
+        "*/15 * * * * *",
+        "2024-01-31 23:59:45",
+        "2024-02-01 00:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn seconds_with_minutes() {
+    assert!(test(
+        "30 10 * * * *",
+        "2024-01-31 22:10:30",
+        "2024-01-31 23:10:30"
+    )
+    .unwrap());
+    assert!(test(
+        "30 10 * * * *",
+        "2024-01-31 22:09:45",
+        "2024-01-31 22:10:30"
+    )
+    .unwrap());
+}
+
+#[test]
+fn seconds_next_month() {
+    assert!(test("15 0 0 1 * *", "2024-01-31 23:59:59", "2024-02-01 00:00:15").unwrap());
+}
+
+#[test]
+fn five_fields_ignore_seconds() {
+    assert!(test("* * * * *", "2024-01-31 23:58:30", "2024-01-31 23:59:00").unwrap());
+    assert!(test("10 * * * *", "2024-01-31 22:09:59", "2024-01-31 22:10:00").unwrap());
+}
+
+#[test]
+fn last_day_of_month() {
+    assert!(test("0 0 L * *", "2024-01-31 00:00:00", "2024-02-29 00:00:00").unwrap());
+    assert!(test("0 0 L * *", "2023-02-01 00:00:00", "2023-02-28 00:00:00").unwrap());
+    assert!(test("0 0 L-3 * *", "2024-04-01 00:00:00", "2024-04-27 00:00:00").unwrap());
+}
+
+#[test]
+fn last_weekday_of_month() {
+    // 2024-03-31 is a Sunday, 2024-08-31 is a Saturday
+    assert!(test("0 0 LW * *", "2024-03-01 00:00:00", "2024-03-29 00:00:00").unwrap());
+    assert!(test("0 0 LW 8 *", "2024-03-01 00:00:00", "2024-08-30 00:00:00").unwrap());
+    assert!(test("0 0 LW * *", "2024-04-01 00:00:00", "2024-04-30 00:00:00").unwrap());
+}
+
+#[test]
+fn nearest_weekday() {
+    // 2024-06-15 is a Saturday, 2024-09-15 is a Sunday
+    assert!(test("0 0 15W 6 *", "2024-01-01 00:00:00", "2024-06-14 00:00:00").unwrap());
+    assert!(test("0 0 15W 9 *", "2024-01-01 00:00:00", "2024-09-16 00:00:00").unwrap());
+    assert!(test("0 0 15W 5 *", "2024-01-01 00:00:00", "2024-05-15 00:00:00").unwrap());
+}
+
+#[test]
+fn nearest_weekday_month_edges() {
+    // 2024-06-01 is a Saturday, 2024-03-31 is a Sunday
+    assert!(test("0 0 1W 6 *", "2024-01-01 00:00:00", "2024-06-03 00:00:00").unwrap());
+    assert!(test("0 0 31W 3 *", "2024-01-01 00:00:00", "2024-03-29 00:00:00").unwrap());
+    // skipped in months without a 31st
+    assert!(test("0 0 31W * *", "2024-04-01 00:00:00", "2024-05-31 00:00:00").unwrap());
+}
+
+#[test]
+fn last_dow_of_month() {
+    assert!(test("0 0 * * 5L", "2024-02-01 00:00:00", "2024-02-23 00:00:00").unwrap());
+    assert!(test("0 0 * * friL", "2024-02-23 00:00:00", "2024-03-29 00:00:00").unwrap());
+    assert!(test("0 0 * * 0L", "2024-03-01 00:00:00", "2024-03-31 00:00:00").unwrap());
+}
+
+#[test]
+fn nth_dow_of_month() {
+    assert!(test("0 0 * * 2#3", "2024-02-01 00:00:00", "2024-02-20 00:00:00").unwrap());
+    assert!(test(
+        "0 0 * * tue#1",
+        "2024-02-20 00:00:00",
+        "2024-03-05 00:00:00"
+    )
+    .unwrap());
+    // skipped in months without a fifth Thursday
+    assert!(test("0 0 * * 4#5", "2024-03-01 00:00:00", "2024-05-30 00:00:00").unwrap());
+}
+
+#[test]
+fn modifiers_with_lists() {
+    assert!(test("0 0 1,L * *", "2024-02-01 00:00:00", "2024-02-29 00:00:00").unwrap());
+    assert!(test("0 0 1,L * *", "2024-02-29 00:00:00", "2024-03-01 00:00:00").unwrap());
+    assert!(test(
+        "0 0 * * 1#1,5L",
+        "2024-02-05 00:00:00",
+        "2024-02-23 00:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn macros() {
+    let pairs = [
+        ("@yearly", "0 0 1 1 *"),
+        ("@annually", "0 0 1 1 *"),
+        ("@monthly", "0 0 1 * *"),
+        ("@weekly", "0 0 * * 0"),
+        ("@daily", "0 0 * * *"),
+        ("@midnight", "0 0 * * *"),
+        ("@HOURLY", "0 * * * *"),
+    ];
+    for (nickname, expanded) in pairs {
+        let expression = Expression::from_str(nickname).unwrap();
+        let parsed = Expression::from_str(expanded).unwrap();
+        assert_eq!(expression.fields, parsed.fields);
+    }
+    assert!(test("@daily", "2024-01-31 10:00:00", "2024-02-01 00:00:00").unwrap());
+}
+
+#[test]
+fn every_interval() {
+    assert!(test("@every 90m", "2024-01-31 23:00:00", "2024-02-01 00:30:00").unwrap());
+    assert!(test(
+        "@every 1h30m15s",
+        "2024-01-31 23:00:00",
+        "2024-02-01 00:30:15"
+    )
+    .unwrap());
+    assert!(test("@every 15s", "2024-01-31 23:59:50", "2024-02-01 00:00:05").unwrap());
+}
+
+#[test]
+fn every_interval_overflow() {
+    assert!(matches!(
+        Expression::from_str("@every 9999999999999999h"),
+        Err(CronError::InvalidInterval {
+            reason: "is too long",
+            ..
+        })
+    ));
+    assert!(Expression::from_str("@every 2562047788015h1h").is_err());
+
+    // parses, but goes past the supported dates
+    let expression = Expression::from_str("@every 2562047788015h").unwrap();
+    let now = utc_from_str("2024-01-31 23:00:00");
+    assert_eq!(expression.next(now), None);
+    assert_eq!(expression.prev(now), None);
+    assert_eq!(expression.next_utc(Utc.from_utc_datetime(&now)), None);
+    assert_eq!(
+        expression.iter_from(Utc.from_utc_datetime(&now)).next(),
+        None
+    );
+}
+
+#[test]
+fn timezone_offset() {
+    // 09:00 in Berlin is 08:00 UTC in winter and 07:00 UTC in summer
+    assert!(test_tz(
+        "0 9 * * *",
+        "Europe/Berlin",
+        "2024-03-29 09:00:00",
+        "2024-03-30 08:00:00"
+    )
+    .unwrap());
+    assert!(test_tz(
+        "0 9 * * *",
+        "Europe/Berlin",
+        "2024-03-30 08:00:00",
+        "2024-03-31 07:00:00"
+    )
+    .unwrap());
+    assert!(test_tz(
+        "0 9 * * *",
+        "Europe/Berlin",
+        "2024-10-26 07:00:00",
+        "2024-10-27 08:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn timezone_skipped_hour() {
+    // 02:30 does not exist on 2024-03-31 in Berlin, fires at 03:30 CEST
+    assert!(test_tz(
+        "30 2 * * *",
+        "Europe/Berlin",
+        "2024-03-30 02:00:00",
+        "2024-03-31 01:30:00"
+    )
+    .unwrap());
+    assert!(test_tz(
+        "30 2 * * *",
+        "Europe/Berlin",
+        "2024-03-31 01:30:00",
+        "2024-04-01 00:30:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn timezone_skipped_hour_fires_every_time() {
+    // 02:00 to 03:00 don't exist on 2024-03-31 in Berlin, all four fire an hour later
+    let expression = Expression::from_str("*/15 2 * * *")
+        .unwrap()
+        .with_timezone("Europe/Berlin")
+        .unwrap();
+    let fires = [
+        "2024-03-31 01:00:00",
+        "2024-03-31 01:15:00",
+        "2024-03-31 01:30:00",
+        "2024-03-31 01:45:00",
+        "2024-04-01 00:00:00",
+    ]
+    .map(utc);
+
+    let mut now = utc("2024-03-31 00:50:00");
+    for fire in fires {
+        now = expression.next_utc(now).unwrap();
+        assert_eq!(now, fire);
+        assert!(expression.matches_utc(fire));
+    }
+    for fire in fires.iter().rev().skip(1) {
+        now = expression.prev_utc(now).unwrap();
+        assert_eq!(now, *fire);
+    }
+    assert_eq!(
+        expression.next_utc(utc("2024-03-31 01:20:00")),
+        Some(utc("2024-03-31 01:30:00"))
+    );
+    assert_eq!(
+        expression.prev_utc(utc("2024-03-31 01:20:00")),
+        Some(utc("2024-03-31 01:15:00"))
+    );
+    for time in [
+        "2024-03-31 00:15:00",
+        "2024-03-31 01:05:00",
+        "2024-03-31 02:00:00",
+    ] {
+        assert!(!expression.matches_utc(utc(time)), "{}", time);
+    }
+}
+
+#[test]
+fn timezone_repeated_hour() {
+    // 02:30 happens twice on 2024-10-27 in Berlin, fires on the first one only
+    assert!(test_tz(
+        "30 2 * * *",
+        "Europe/Berlin",
+        "2024-10-26 23:00:00",
+        "2024-10-27 00:30:00"
+    )
+    .unwrap());
+    assert!(test_tz(
+        "30 2 * * *",
+        "Europe/Berlin",
+        "2024-10-27 00:30:00",
+        "2024-10-28 01:30:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn timezone_interval_ignores_dst() {
+    assert!(test_tz(
+        "@every 1h",
+        "Europe/Berlin",
+        "2024-03-31 00:30:00",
+        "2024-03-31 01:30:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn ends_of_time() {
+    let expressions = [
+        ("* * * * *", None),
+        ("0 0 1 1 *", None),
+        ("0 0 29 2 *", None),
+        ("0 0 L * 5#3", None),
+        ("0 0 0 1 1 * 2099", None),
+        ("30 2 * * *", Some("Europe/Berlin")),
+        ("*/15 * * * *", Some("Asia/Kolkata")),
+        ("@every 1h", None),
+    ];
+    for (expression, timezone) in expressions {
+        let mut expression = Expression::from_str(expression).unwrap();
+        if let Some(timezone) = timezone {
+            expression = expression.with_timezone(timezone).unwrap();
+        }
+        for policy in ["skip", "shift"] {
+            let calendar = Calendar::new([]).with_holiday_policy(policy).unwrap();
+            let with_calendar = expression.clone().with_calendar(calendar);
+            with_calendar.next_utc(DateTime::<Utc>::MAX_UTC);
+            with_calendar.prev_utc(DateTime::<Utc>::MIN_UTC);
+            with_calendar.next_utc(DateTime::<Utc>::MIN_UTC);
+            with_calendar.prev_utc(DateTime::<Utc>::MAX_UTC);
+        }
+        assert_eq!(expression.next(NaiveDateTime::MAX), None, "{}", expression);
+        assert_eq!(expression.prev(NaiveDateTime::MIN), None, "{}", expression);
+        let (min, max) = (DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC);
+        assert_eq!(expression.next_utc(max), None, "{}", expression);
+        assert_eq!(expression.prev_utc(min), None, "{}", expression);
+        // and the other way round, with the whole range to search
+        expression.next_utc(min);
+        expression.prev_utc(max);
+        expression.matches_utc(min);
+        expression.matches_utc(max);
+    }
+}
+
+#[test]
+fn dow_wrap_year() {
+    assert!(test("0 0 * * 1", "2024-12-30 00:00:00", "2025-01-06 00:00:00").unwrap());
+}
+
+#[test]
+fn dow_end_of_month() {
+    // October 2023 starts on a Sunday
+    assert!(test("0 0 * * 1", "2023-10-26 21:36:11", "2023-10-30 00:00:00").unwrap());
+    assert!(test("0 0 * * 0", "2023-12-30 22:18:42", "2023-12-31 00:00:00").unwrap());
+    assert!(test_prev("0 0 * * 6#5", "2026-01-15 00:00:00", "2025-11-29 00:00:00").unwrap());
+}
+
+#[test]
+fn prev_every_minute() {
+    assert!(test_prev("* * * * *", "2024-03-01 00:00:00", "2024-02-29 23:59:00").unwrap());
+    assert!(test_prev("* * * * *", "2024-03-01 00:00:30", "2024-03-01 00:00:00").unwrap());
+}
+
+#[test]
+fn prev_minutes_list() {
+    assert!(test_prev(
+        "10,20 * * * *",
+        "2024-01-31 23:15:00",
+        "2024-01-31 23:10:00"
+    )
+    .unwrap());
+    assert!(test_prev(
+        "10,20 * * * *",
+        "2024-01-31 23:10:00",
+        "2024-01-31 22:20:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn prev_month_wrapping() {
+    assert!(test_prev("0 0 31 * *", "2024-05-01 00:00:00", "2024-03-31 00:00:00").unwrap());
+    assert!(test_prev("0 0 L * *", "2024-03-01 00:00:00", "2024-02-29 00:00:00").unwrap());
+    assert!(test_prev("0 0 L * *", "2023-03-01 00:00:00", "2023-02-28 00:00:00").unwrap());
+}
+
+#[test]
+fn prev_year_wrapping() {
+    assert!(test_prev("0 0 * * 1", "2024-01-01 00:00:00", "2023-12-25 00:00:00").unwrap());
+    assert!(test_prev("0 12 1 jan *", "2024-01-01 11:00:00", "2023-01-01 12:00:00").unwrap());
+}
+
+#[test]
+fn prev_leap_year() {
+    assert!(test_prev("* * 29 2 *", "2027-03-01 10:59:00", "2024-02-29 23:59:00").unwrap());
+}
+
+#[test]
+fn prev_dow_and_dom() {
+    assert!(test_prev(
+        "* * 15,20 * 3",
+        "2024-02-15 00:00:00",
+        "2024-02-14 23:59:00"
+    )
+    .unwrap());
+    assert!(test_prev(
+        "0 0 15,20 * 3",
+        "2024-02-07 00:00:00",
+        "2024-01-31 00:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn prev_seconds() {
+    assert!(test_prev(
+        "*/15 * * * * *",
+        "2024-01-01 00:00:00",
+        "2023-12-31 23:59:45"
+    )
+    .unwrap());
+}
+
+#[test]
+fn prev_interval() {
+    assert!(test_prev("@every 90m", "2024-02-01 00:30:00", "2024-01-31 23:00:00").unwrap());
+}
+
+#[test]
+fn prev_timezone() {
+    let expression = Expression::from_str("30 2 * * *")
+        .unwrap()
+        .with_timezone("Europe/Berlin")
+        .unwrap();
+    let prev = |s| expression.prev_utc(Utc.from_utc_datetime(&utc_from_str(s)));
+
+    // skipped 02:30 fired at 03:30 CEST
+    let expected = Utc.from_utc_datetime(&utc_from_str("2024-03-31 01:30:00"));
+    assert_eq!(prev("2024-03-31 02:00:00"), Some(expected));
+
+    // repeated 02:30 fired on its first occurrence only
+    let expected = Utc.from_utc_datetime(&utc_from_str("2024-10-27 00:30:00"));
+    assert_eq!(prev("2024-10-27 02:00:00"), Some(expected));
+}
+
+#[test]
+fn iter_from() {
+    let expression = Expression::from_str("0 0 L * *").unwrap();
+    let start = Utc.from_utc_datetime(&utc_from_str("2024-01-31 00:00:00"));
+    let expected = [
+        "2024-02-29 00:00:00",
+        "2024-03-31 00:00:00",
+        "2024-04-30 00:00:00",
+    ]
+    .map(|s| Utc.from_utc_datetime(&utc_from_str(s)));
+    assert!(expression.iter_from(start).take(3).eq(expected));
+}
+
+#[test]
+fn iter_matches_next() {
+    let expression = Expression::from_str("1-2,*/15 0 1 3 2").unwrap();
+    let mut now = utc_from_str("2024-01-31 20:00:00");
+    for next in expression.iter_from(Utc.from_utc_datetime(&now)).take(50) {
+        now = expression.next(now).unwrap();
+        assert_eq!(next.naive_utc(), now);
+    }
+}
+
+#[test]
+fn between() {
+    let expression = Expression::from_str("0 */6 * * *").unwrap();
+    let start = Utc.from_utc_datetime(&utc_from_str("2024-01-01 00:00:00"));
+    let end = Utc.from_utc_datetime(&utc_from_str("2024-01-02 00:00:00"));
+    let expected = [
+        "2024-01-01 00:00:00",
+        "2024-01-01 06:00:00",
+        "2024-01-01 12:00:00",
+        "2024-01-01 18:00:00",
+    ]
+    .map(|s| Utc.from_utc_datetime(&utc_from_str(s)));
+    assert!(expression.between(start, end).eq(expected));
+}
+
+#[test]
+fn count_between() {
+    let start = Utc.from_utc_datetime(&utc_from_str("2024-01-01 00:00:00"));
+    let end = Utc.from_utc_datetime(&utc_from_str("2025-01-01 00:00:00"));
+    let expression = Expression::from_str("0 0 * * *").unwrap();
+    assert_eq!(expression.count_between(start, end), 366);
+    let expression = Expression::from_str("*/15 * * * *").unwrap();
+    assert_eq!(expression.count_between(start, end), 366 * 24 * 4);
+    let expression = Expression::from_str("0 9 * * *")
+        .unwrap()
+        .with_timezone("Europe/Berlin")
+        .unwrap();
+    assert_eq!(expression.count_between(start, end), 366);
+}
+
+#[test]
+fn matches() {
+    let expression = Expression::from_str("1-2,*/15 0 1 3 2").unwrap();
+    assert!(expression.matches(utc_from_str("2024-03-01 00:15:00")));
+    assert!(expression.matches(utc_from_str("2024-03-05 00:02:00")));
+    assert!(!expression.matches(utc_from_str("2024-03-05 00:03:00")));
+    assert!(!expression.matches(utc_from_str("2024-03-06 00:15:00")));
+    assert!(!expression.matches(utc_from_str("2024-04-01 00:15:00")));
+    assert!(!expression.matches(utc_from_str("2024-03-01 00:15:30")));
+}
+
+#[test]
+fn matches_dom_dow_interaction() {
+    // restricted dow and unrestricted dom only matches the dow
+    let expression = Expression::from_str("0 0 * * 1").unwrap();
+    assert!(expression.matches(utc_from_str("2024-01-08 00:00:00")));
+    assert!(!expression.matches(utc_from_str("2024-01-09 00:00:00")));
+    // both restricted matches either one
+    let expression = Expression::from_str("0 0 13 * 5").unwrap();
+    assert!(expression.matches(utc_from_str("2024-09-13 00:00:00")));
+    assert!(expression.matches(utc_from_str("2024-09-06 00:00:00")));
+    assert!(expression.matches(utc_from_str("2024-08-13 00:00:00")));
+    assert!(!expression.matches(utc_from_str("2024-08-14 00:00:00")));
+}
+
+#[test]
+fn matches_modifiers() {
+    let expression = Expression::from_str("0 0 L * *").unwrap();
+    assert!(expression.matches(utc_from_str("2024-02-29 00:00:00")));
+    assert!(!expression.matches(utc_from_str("2024-02-28 00:00:00")));
+    let expression = Expression::from_str("0 0 * * 2#3").unwrap();
+    assert!(expression.matches(utc_from_str("2024-02-20 00:00:00")));
+    assert!(!expression.matches(utc_from_str("2024-02-13 00:00:00")));
+}
+
+#[test]
+fn matches_next() {
+    let expression = Expression::from_str("*/7 3 1-10/3 */2 mon").unwrap();
+    let mut now = utc_from_str("2024-01-01 00:00:00");
+    for _ in 0..100 {
+        now = expression.next(now).unwrap();
+        assert!(expression.matches(now));
+        assert!(!expression.matches(now - chrono::Duration::seconds(1)));
+    }
+}
+
+#[test]
+fn matches_timezone() {
+    let expression = Expression::from_str("30 2 * * *")
+        .unwrap()
+        .with_timezone("Europe/Berlin")
+        .unwrap();
+    let matches = |s| expression.matches_utc(Utc.from_utc_datetime(&utc_from_str(s)));
+    assert!(matches("2024-01-10 01:30:00"));
+    assert!(matches("2024-07-10 00:30:00"));
+    assert!(!matches("2024-07-10 01:30:00"));
+    // skipped 02:30 fires at 03:30 CEST
+    assert!(matches("2024-03-31 01:30:00"));
+    // repeated 02:30 fires on its first occurrence only
+    assert!(matches("2024-10-27 00:30:00"));
+    assert!(!matches("2024-10-27 01:30:00"));
+}
+
+#[test]
+fn describe_year() {
+    let describe = |s| Expression::from_str(s).unwrap().describe();
+    assert_eq!(
+        describe("0 30 9 * * 1-5 2027"),
+        "At 09:30, on Monday through Friday in year 2027"
+    );
+    assert_eq!(
+        describe("at:2026-12-31T23:00"),
+        "At 23:00, on day 31 in December in year 2026"
+    );
+}
+
+#[test]
+fn describe_day_semantics() {
+    let expression = Expression::from_str("0 0 13 * fri").unwrap();
+    assert_eq!(
+        expression.with_day_semantics("and").unwrap().describe(),
+        "At 00:00, on day 13 if it falls on Fridays"
+    );
+}
+
+#[test]
+fn describe() {
+    let describe = |s| Expression::from_str(s).unwrap().describe();
+    assert_eq!(
+        describe("1-2,*/15 0 1 3 2"),
+        "At minutes 1, 2 and every 15 minutes past hour 0, on day 1 and on Tuesdays in March"
+    );
+    assert_eq!(describe("* * * * *"), "At every minute");
+    assert_eq!(
+        describe("30 9 * * mon-fri"),
+        "At 09:30, on Monday through Friday"
+    );
+    assert_eq!(
+        describe("0-30/15 */2 * jan,jun *"),
+        "At every 15 minutes from 0 through 30 past every 2 hours in January and June"
+    );
+    assert_eq!(describe("*/15 * * * * *"), "At every 15 seconds");
+    assert_eq!(
+        describe("15 30 9 1-15 * *"),
+        "At 09:30:15, on days 1 through 15"
+    );
+    assert_eq!(
+        describe("0 0 L-3,15W * *"),
+        "At 00:00, on 3 days before the last day of the month and the weekday nearest day 15"
+    );
+    assert_eq!(
+        describe("0 0 * * 2#3,5L"),
+        "At 00:00, on the third Tuesday of the month and the last Friday of the month"
+    );
+    assert_eq!(describe("@every 1h30m"), "Every 1 hour and 30 minutes");
+}
+
+#[test]
+fn canonical_form() {
+    let canonical = |s| Expression::from_str(s).unwrap().to_string();
+    assert_eq!(canonical("0 0 20 feb *"), "0 0 20 2 *");
+    assert_eq!(canonical("0 0 * * friL"), "0 0 * * 5L");
+    assert_eq!(canonical("15 0 0 * * *"), "15 0 0 * * *");
+    assert_eq!(canonical("0 0 0 * * *"), "0 0 * * *");
+    assert_eq!(canonical("@daily"), "0 0 * * *");
+    assert_eq!(canonical("@every 90m"), "@every 1h30m");
+}
+
+#[test]
+fn canonical_form_round_trip() {
+    let now = utc_from_str("2026-01-01 00:00:00");
+    for expression in [
+        "0 0 0 1 1 * 2027",
+        "30 0 9 * * mon-fri 2027-2028",
+        "at:2026-12-31T23:00",
+        "at: 2027-03-01 08:30:15",
+        "15 0 0 * * *",
+        "0 0 L * *",
+        "@every 90m",
+    ] {
+        let expression = Expression::from_str(expression).unwrap();
+        let parsed = Expression::from_str(&expression.to_string()).unwrap();
+        assert_eq!(parsed.to_string(), expression.to_string());
+        assert_eq!(parsed.next(now), expression.next(now), "{}", expression);
+    }
+}
+
 #[test]
 fn should_fail() {
     assert!(test("0 0 30 2 *", "", "").is_err()); // non-existent date
@@ -352,7 +1022,493 @@ fn should_fail() {
     assert!(test("0 0 * -1 *", "", "").is_err()); // invalid value
     assert!(test("0 0 * mon-wed *", "", "").is_err()); // wrong field
     assert!(test("0 0 * * feb", "", "").is_err()); // wrong field
-    assert!(test("0 0 * jun-jan *", "", "").is_err()); // wrong order
+    assert!(test("0 0 * jun-jan-feb *", "", "").is_err()); // double range
+    assert!(test("60 0 0 * * *", "", "").is_err()); // invalid second
+    assert!(test("0 0 0 * * * * *", "", "").is_err()); // too many fields
+    assert!(test("0 0 32W * *", "", "").is_err()); // invalid nearest weekday
+    assert!(test("0 0 L-31 * *", "", "").is_err()); // invalid offset
+    assert!(test("0 0 * * 2#6", "", "").is_err()); // invalid occurrence
+    assert!(test("0 0 * * 8L", "", "").is_err()); // invalid day of week
+    assert!(test("0 0 * L *", "", "").is_err()); // wrong field
+    assert!(test("@fortnightly", "", "").is_err()); // unknown macro
+    assert!(test("@every 10", "", "").is_err()); // interval without unit
+    assert!(test("@every 1d", "", "").is_err()); // unknown interval unit
+    assert!(test("@every 0s", "", "").is_err()); // empty interval
+    assert!(test_tz("* * * * *", "Mars/Olympus_Mons", "", "").is_err()); // unknown timezone
+}
+
+#[test]
+fn day_semantics_and() {
+    // Friday the 13th
+    assert!(test_and("0 0 13 * fri", "2024-01-01 00:00:00", "2024-09-13 00:00:00").unwrap());
+    // first Monday of the month
+    assert!(test_and(
+        "0 0 1-7 * mon",
+        "2024-03-05 00:00:00",
+        "2024-04-01 00:00:00"
+    )
+    .unwrap());
+    // last day of the month if it's a weekend
+    assert!(test_and(
+        "0 0 L * sat,sun",
+        "2024-01-01 00:00:00",
+        "2024-03-31 00:00:00"
+    )
+    .unwrap());
+    // unrestricted fields don't filter anything
+    assert!(test_and("0 0 * * mon", "2024-03-01 00:00:00", "2024-03-04 00:00:00").unwrap());
+    assert!(test_and("0 0 15 * *", "2024-03-01 00:00:00", "2024-03-15 00:00:00").unwrap());
+    // leap days on a Monday are decades apart
+    assert!(test_and("0 0 29 2 mon", "2024-03-01 00:00:00", "2044-02-29 00:00:00").unwrap());
+}
+
+#[test]
+fn day_semantics_or() {
+    let expression = Expression::from_str("0 0 13 * fri").unwrap();
+    let or = expression.clone().with_day_semantics("OR").unwrap();
+    let t = utc_from_str("2024-03-01 00:00:00");
+    assert_eq!(expression.next(t), or.next(t));
+    assert_eq!(or.next(t), Some(utc_from_str("2024-03-08 00:00:00")));
+}
+
+#[test]
+fn day_semantics_prev_matches() {
+    let expression = Expression::from_str("0 0 13 * fri")
+        .unwrap()
+        .with_day_semantics("and")
+        .unwrap();
+    let t = utc_from_str("2024-09-13 00:00:00");
+    assert_eq!(
+        expression.prev(t),
+        Some(utc_from_str("2023-10-13 00:00:00"))
+    );
+    assert!(expression.matches(t));
+    assert!(!expression.matches(utc_from_str("2024-03-08 00:00:00")));
+}
+
+#[test]
+fn day_semantics_invalid() {
+    let expression = Expression::from_str("0 0 30 2 mon").unwrap();
+    // Mondays in February, but never a Monday Feb-30
+    assert!(expression.clone().with_day_semantics("and").is_err());
+    assert!(expression.with_day_semantics("xor").is_err());
+    assert!(Expression::from_str("0 0 31 * mon")
+        .unwrap()
+        .with_day_semantics("and")
+        .is_ok());
+}
+
+#[test]
+fn year_field() {
+    assert!(test(
+        "0 0 0 1 1 * 2027",
+        "2024-03-01 00:00:00",
+        "2027-01-01 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 0 * * * 2027",
+        "2027-06-30 23:59:00",
+        "2027-07-01 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 0 1 1 * 2030/5",
+        "2031-01-01 00:00:00",
+        "2035-01-01 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 0 29 2 * 2025-2030",
+        "2024-03-01 00:00:00",
+        "2028-02-29 00:00:00"
+    )
+    .unwrap());
+    assert!(test_prev(
+        "0 0 0 1 1 * 2027",
+        "2030-03-01 00:00:00",
+        "2027-01-01 00:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn year_field_exhausted() {
+    let expression = Expression::from_str("0 0 0 * * * 2020-2021").unwrap();
+    assert_eq!(expression.next(utc_from_str("2021-12-31 00:00:00")), None);
+    assert_eq!(expression.prev(utc_from_str("2020-01-01 00:00:00")), None);
+
+    let start = Utc.from_utc_datetime(&utc_from_str("2021-12-29 12:00:00"));
+    assert_eq!(expression.iter_from(start).count(), 2);
+    // valid even if it already fired, but never firing is still an error
+    assert!(Expression::from_str("0 0 0 30 2 * 2020-2030").is_err());
+    assert!(Expression::from_str("0 0 0 1 1 * 2100").is_err());
+}
+
+#[test]
+fn at_one_shot() {
+    let expression = Expression::from_str("at:2026-12-31T23:00").unwrap();
+    let next = expression.next(utc_from_str("2026-01-01 00:00:00"));
+    assert_eq!(next, Some(utc_from_str("2026-12-31 23:00:00")));
+    assert_eq!(expression.next(next.unwrap()), None);
+    assert!(expression.matches(utc_from_str("2026-12-31 23:00:00")));
+    assert_eq!(expression.to_string(), "0 0 23 31 12 * 2026");
+
+    let expression = Expression::from_str("at: 2027-03-01 08:30:15").unwrap();
+    let next = expression.next(utc_from_str("2026-01-01 00:00:00"));
+    assert_eq!(next, Some(utc_from_str("2027-03-01 08:30:15")));
+
+    assert!(Expression::from_str("at:2026-02-30T10:00").is_err());
+    assert!(Expression::from_str("at:2026-12-31").is_err());
+    assert!(Expression::from_str("at:2026-12-31T23:00+01:00").is_err());
+}
+
+#[test]
+fn at_timezone() {
+    let expression = Expression::from_str("at:2026-12-31T23:00")
+        .unwrap()
+        .with_timezone("Europe/Berlin")
+        .unwrap();
+    let now = Utc.from_utc_datetime(&utc_from_str("2026-01-01 00:00:00"));
+    let expected = Utc.from_utc_datetime(&utc_from_str("2026-12-31 22:00:00"));
+    assert_eq!(expression.next_utc(now), Some(expected));
+    assert_eq!(expression.next_utc(expected), None);
+}
+
+#[test]
+fn composite_union() {
+    let schedule = composite("*/10 9-17 * * mon-fri").union(composite("0 2 * * *"));
+    let next = |input| composite_next(&schedule, input);
+
+    assert_eq!(
+        next("2024-03-01 00:00:00"),
+        Some(utc_from_str("2024-03-01 02:00:00"))
+    );
+    assert_eq!(
+        next("2024-03-01 02:00:00"),
+        Some(utc_from_str("2024-03-01 09:00:00"))
+    );
+    assert_eq!(
+        next("2024-03-01 17:50:00"),
+        Some(utc_from_str("2024-03-02 02:00:00"))
+    );
+}
+
+#[test]
+fn composite_except() {
+    // every 10 minutes during business hours plus 02:00 nightly, except on the first
+    let schedule = composite("*/10 9-17 * * *")
+        .union(composite("0 2 * * *"))
+        .except(composite("* * 1 * *"));
+    let next = |input| composite_next(&schedule, input);
+
+    assert_eq!(
+        next("2024-02-29 17:50:00"),
+        Some(utc_from_str("2024-03-02 02:00:00"))
+    );
+    assert_eq!(
+        next("2024-03-02 02:00:00"),
+        Some(utc_from_str("2024-03-02 09:00:00"))
+    );
+
+    let time = |input| Utc.from_utc_datetime(&utc_from_str(input));
+    assert!(schedule.matches_utc(time("2024-03-02 09:10:00")));
+    assert!(!schedule.matches_utc(time("2024-03-01 09:10:00")));
+}
+
+#[test]
+fn composite_intersect() {
+    let schedule = composite("0 0 * * fri").intersect(composite("0 0 13 * *"));
+    let next = |input| composite_next(&schedule, input);
+    assert_eq!(
+        next("2024-01-01 00:00:00"),
+        Some(utc_from_str("2024-09-13 00:00:00"))
+    );
+
+    // fires at different times across timezones
+    let berlin = Expression::from_str("0 1 * * *")
+        .unwrap()
+        .with_timezone("Europe/Berlin")
+        .unwrap();
+    let schedule = composite("0 0 * * *").intersect(berlin.into());
+    assert_eq!(
+        composite_next(&schedule, "2024-03-01 00:00:00"),
+        Some(utc_from_str("2024-03-02 00:00:00"))
+    );
+}
+
+#[test]
+fn composite_never_fires() {
+    let schedule = composite("0 0 * * *").intersect(composite("30 0 * * *"));
+    assert_eq!(composite_next(&schedule, "2024-03-01 00:00:00"), None);
+
+    let schedule = composite("0 0 * * *").except(composite("0 * * * *"));
+    assert_eq!(composite_next(&schedule, "2024-03-01 00:00:00"), None);
+
+    // exhausted expressions end the union
+    let schedule = composite("at:2024-03-02T00:00").union(composite("at:2024-03-03T00:00"));
+    assert_eq!(
+        composite_next(&schedule, "2024-03-02 00:00:00"),
+        Some(utc_from_str("2024-03-03 00:00:00"))
+    );
+    assert_eq!(composite_next(&schedule, "2024-03-03 00:00:00"), None);
+}
+
+#[test]
+fn composite_except_spans() {
+    let berlin = |expression| {
+        let expression = Expression::from_str(expression).unwrap();
+        expression.with_timezone("Europe/Berlin").unwrap()
+    };
+    // exclusions spanning days and DST changes, and the hour repeated in October
+    // that Berlin expressions don't fire on again
+    let cases = [
+        (
+            "* * * * * *",
+            Expression::from_str("* * * * * mon-fri").unwrap(),
+            "2024-03-04 10:00:00",
+        ),
+        (
+            "0 * * * *",
+            berlin("* * * * sun-fri"),
+            "2024-03-30 23:00:00",
+        ),
+        (
+            "*/20 * * * *",
+            berlin("* 0-22 * * *"),
+            "2024-03-30 12:00:00",
+        ),
+        ("* * * * *", berlin("* * * * *"), "2024-10-20 00:00:00"),
+        ("30 * * * * *", berlin("* * * * * *"), "2024-10-26 12:00:00"),
+    ];
+
+    for (node, excluded, start) in cases {
+        let start = Utc.from_utc_datetime(&utc_from_str(start));
+        let schedule = composite(node).except(excluded.clone().into());
+        // every fire time of the node, checked one by one
+        let expected = Expression::from_str(node)
+            .unwrap()
+            .iter_from(start)
+            .find(|time| !excluded.matches_utc(*time));
+        assert_eq!(
+            schedule.next_utc(start),
+            expected,
+            "{} - {}",
+            node,
+            excluded
+        );
+    }
+}
+
+#[test]
+fn composite_except_unions() {
+    // every second except on weekdays and Saturdays, so on Sunday
+    let schedule =
+        composite("* * * * * *").except(composite("* * * * * 1-5").union(composite("* * * * * 6")));
+    assert_eq!(
+        composite_next(&schedule, "2024-03-04 10:00:00"),
+        Some(utc_from_str("2024-03-10 00:00:00"))
+    );
+
+    // every minute except in January through November and on December 1-30
+    let schedule =
+        composite("* * * * *").except(composite("* * * 1-11 *").union(composite("* * 1-30 12 *")));
+    assert_eq!(
+        composite_next(&schedule, "2024-01-01 00:00:00"),
+        Some(utc_from_str("2024-12-31 00:00:00"))
+    );
+    assert_eq!(
+        composite_next(&schedule, "2024-12-31 23:59:00"),
+        Some(utc_from_str("2025-12-31 00:00:00"))
+    );
+
+    // intersections are covered until the first schedule stops
+    let schedule = composite("*/30 * * * * *")
+        .except(composite("* * 0-11 * * *").intersect(composite("* * * * * mon-fri")));
+    assert_eq!(
+        composite_next(&schedule, "2024-03-04 00:00:00"),
+        Some(utc_from_str("2024-03-04 12:00:00"))
+    );
+}
+
+#[test]
+fn composite_never_fires_quickly() {
+    let start = std::time::Instant::now();
+    for (schedule, excluded) in [
+        ("* * * * *", "* * * * *"),
+        ("* * * * * *", "* * * * * *"),
+        ("0 0 * * *", "* * * * *"),
+    ] {
+        let schedule = composite(schedule).except(composite(excluded));
+        assert_eq!(composite_next(&schedule, "2024-03-01 00:00:00"), None);
+    }
+
+    // gives up instead of stepping through decades of fire times
+    let schedule = composite("0 * * * * *").intersect(composite("30 * * * * *"));
+    assert_eq!(composite_next(&schedule, "2024-03-01 00:00:00"), None);
+
+    // unions of exclusions are skipped a day at a time until the horizon
+    let schedule = composite("* * * * * *")
+        .except(composite("* * * * * 0-3").union(composite("* * * * * 4-6")));
+    assert_eq!(composite_next(&schedule, "2024-03-01 00:00:00"), None);
+    let schedule = composite("* * * * *")
+        .except(composite("* * * * 1-5").intersect(composite("* * * * *")))
+        .except(composite("* * * * 0,6"));
+    assert_eq!(composite_next(&schedule, "2024-03-01 00:00:00"), None);
+
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[test]
+fn vixie_sunday_seven() {
+    assert!(test("0 0 * * 7", "2024-03-01 00:00:00", "2024-03-03 00:00:00").unwrap());
+    assert!(test("0 0 * * 5-7", "2024-03-03 00:00:00", "2024-03-08 00:00:00").unwrap());
+    assert!(test("0 0 * * 7-2", "2024-03-03 00:00:00", "2024-03-04 00:00:00").unwrap());
+    assert!(test("0 0 * * 0-7", "2024-03-03 00:00:00", "2024-03-04 00:00:00").unwrap());
+    assert!(test("0 0 * * 7L", "2024-03-01 00:00:00", "2024-03-31 00:00:00").unwrap());
+}
+
+#[test]
+fn vixie_start_at_step() {
+    assert!(test("5/15 * * * *", "2024-03-01 00:06:00", "2024-03-01 00:20:00").unwrap());
+    assert!(test("5/15 * * * *", "2024-03-01 00:50:00", "2024-03-01 01:05:00").unwrap());
+    assert!(test(
+        "0 0 10/10 * *",
+        "2024-02-20 00:00:00",
+        "2024-03-10 00:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn vixie_wrap_around() {
+    assert!(test("0 22-2 * * *", "2024-03-01 02:00:00", "2024-03-01 22:00:00").unwrap());
+    assert!(test("0 22-2 * * *", "2024-03-01 23:00:00", "2024-03-02 00:00:00").unwrap());
+    // Friday 2024-03-01 through Monday
+    assert!(test(
+        "0 0 * * fri-mon",
+        "2024-02-27 00:00:00",
+        "2024-03-01 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 * * fri-mon",
+        "2024-03-04 00:00:00",
+        "2024-03-08 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 * * fri-mon/2",
+        "2024-03-01 00:00:00",
+        "2024-03-03 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 1 nov-feb *",
+        "2024-03-01 00:00:00",
+        "2024-11-01 00:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn vixie_name_steps() {
+    assert!(test(
+        "0 0 1 jan-dec/3 *",
+        "2024-01-01 00:00:00",
+        "2024-04-01 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 1 FEB/4 *",
+        "2024-02-01 00:00:00",
+        "2024-06-01 00:00:00"
+    )
+    .unwrap());
+    assert!(test(
+        "0 0 * * Mon-Fri/2",
+        "2024-03-04 00:00:00",
+        "2024-03-06 00:00:00"
+    )
+    .unwrap());
+}
+
+#[test]
+fn vixie_rejects_malformed() {
+    let fails = |expression| Expression::from_str(expression).is_err();
+
+    assert!(fails("0 0 * * sunday")); // names are three letters
+    assert!(fails("0 0 * janfeb *")); // no separator
+    assert!(fails("0 0 * * 1mon")); // no separator
+    assert!(fails("+5 * * * *")); // sign
+    assert!(fails("1,,2 * * * *")); // empty part
+    assert!(fails("1- * * * *")); // open range
+    assert!(fails("*-5 * * * *")); // star range
+    assert!(fails("*/5/2 * * * *")); // double step
+    assert!(fails("*/0 * * * *")); // zero step
+    assert!(fails("1-5/ * * * *")); // missing step
+    assert!(fails("0 0 * * L")); // dom modifier in dow
+    assert!(fails("0 0 5L * *")); // dow modifier in dom
+    assert!(fails("0 0 * * mon#")); // missing occurrence
+}
+
+#[test]
+fn vixie_canonical_form() {
+    let canonical = |expression| Expression::from_str(expression).unwrap().to_string();
+
+    assert_eq!(
+        canonical("05/15 * * jan-dec/3 Fri-Mon"),
+        "5/15 * * 1-12/3 5-1"
+    );
+    assert_eq!(canonical("0 0 lw * 7l"), "0 0 LW * 7L");
+}
+
+#[test]
+fn error_position() {
+    let position = |expression| {
+        let error = Expression::from_str(expression).unwrap_err();
+        error
+            .position()
+            .map(|at| (at.field.clone(), at.part, at.offset))
+    };
+
+    assert_eq!(position("0 0 1,2,x * *"), Some((Unit::Dom, 2, 8)));
+    assert_eq!(position("0 0 * 1-13 *"), Some((Unit::Month, 0, 8)));
+    assert_eq!(position("30 0 0 * * mon,2#6"), Some((Unit::Dow, 1, 17)));
+    assert_eq!(position("  */0 * * * *"), Some((Unit::Minute, 0, 4)));
+    assert_eq!(position("0 0 * feb-fri *"), Some((Unit::Month, 0, 10)));
+    assert_eq!(position("0 0 0 * * * * *"), None);
+    assert_eq!(position("0 0 30 2 *"), None);
+}
+
+#[test]
+fn error_kind() {
+    let error = |expression| Expression::from_str(expression).unwrap_err();
+
+    assert!(matches!(error("0 0 0 * * * * *"), CronError::FieldCount(8)));
+    assert!(matches!(error("0 0 30 2 *"), CronError::NeverFires));
+    assert!(matches!(error("@fortnightly"), CronError::UnknownMacro(_)));
+    assert!(matches!(
+        error("@every 1d"),
+        CronError::InvalidInterval { .. }
+    ));
+    assert!(matches!(
+        error("5-/15 * * * *"),
+        CronError::InvalidPart { .. }
+    ));
+    assert!(matches!(
+        error("0 24 * * *"),
+        CronError::OutOfRange {
+            value: 24,
+            min: 0,
+            max: 23,
+            ..
+        }
+    ));
+    assert_eq!(
+        error("0 24 * * *").to_string(),
+        "Value 24 for field hour must be between 0 and 23 (part 0, offset 2)"
+    );
 }
 
 #[test]
@@ -367,3 +1523,530 @@ fn non_numeric_expression() {
     let parsed = Expression::from_str("0 0 20 * 1-3").unwrap();
     assert_eq!(expression.fields, parsed.fields);
 }
+
+#[test]
+fn calendar_skip() {
+    let expression = Expression::from_str("0 9 * * *")
+        .unwrap()
+        .with_calendar(christmas());
+    let next = |now| expression.next_utc(utc(now));
+
+    assert_eq!(
+        next("2024-12-24 10:00:00"),
+        Some(utc("2024-12-27 09:00:00"))
+    );
+    // weekends aren't business days either
+    assert_eq!(
+        next("2024-12-27 10:00:00"),
+        Some(utc("2024-12-30 09:00:00"))
+    );
+    assert_eq!(
+        expression.prev_utc(utc("2024-12-27 08:00:00")),
+        Some(utc("2024-12-24 09:00:00"))
+    );
+    assert!(!expression.matches_utc(utc("2024-12-25 09:00:00")));
+    assert!(expression.matches_utc(utc("2024-12-27 09:00:00")));
+}
+
+#[test]
+fn calendar_shift() {
+    let calendar = christmas().with_holiday_policy("shift").unwrap();
+    let expression = Expression::from_str("0 9 25 12 *")
+        .unwrap()
+        .with_calendar(calendar.clone());
+    let next = |now| expression.next_utc(utc(now));
+
+    assert_eq!(
+        next("2024-12-24 00:00:00"),
+        Some(utc("2024-12-27 09:00:00"))
+    );
+    // already past the original time, but not the shifted one
+    assert_eq!(
+        next("2024-12-25 10:00:00"),
+        Some(utc("2024-12-27 09:00:00"))
+    );
+    assert_eq!(
+        next("2024-12-27 09:00:00"),
+        Some(utc("2025-12-25 09:00:00"))
+    );
+    assert_eq!(
+        expression.prev_utc(utc("2025-01-01 00:00:00")),
+        Some(utc("2024-12-27 09:00:00"))
+    );
+    assert!(expression.matches_utc(utc("2024-12-27 09:00:00")));
+    assert!(!expression.matches_utc(utc("2024-12-25 09:00:00")));
+
+    // fire times moved to the same day only fire once
+    let daily = Expression::from_str("0 9 * * *")
+        .unwrap()
+        .with_calendar(calendar);
+    assert_eq!(
+        daily.next_utc(utc("2024-12-27 09:00:00")),
+        Some(utc("2024-12-30 09:00:00"))
+    );
+}
+
+#[test]
+fn calendar_timezone() {
+    // midnight in Berlin is still the 24th in UTC
+    let expression = Expression::from_str("0 0 * * *")
+        .unwrap()
+        .with_timezone("Europe/Berlin")
+        .unwrap()
+        .with_calendar(christmas().with_weekends(false));
+
+    assert_eq!(
+        expression.next_utc(utc("2024-12-24 12:00:00")),
+        Some(utc("2024-12-26 23:00:00"))
+    );
+}
+
+#[test]
+fn calendar_files() {
+    let dir = std::env::temp_dir();
+    let ics = dir.join(format!("tm-calendar-{}.ics", std::process::id()));
+    let json = dir.join(format!("tm-calendar-{}.json", std::process::id()));
+
+    std::fs::write(
+        &ics,
+        "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20241225\r\n\
+         DTEND;VALUE=DATE:20241227\r\nSUMMARY:Christmas\r\nEND:VEVENT\r\n\
+         BEGIN:VEVENT\r\nDTSTART:20250101T000000Z\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &json,
+        r#"{"holidays": ["2024-12-25", {"date": "2024-12-26", "name": "Boxing Day"}]}"#,
+    )
+    .unwrap();
+
+    let ics_calendar = Calendar::from_file(&ics).unwrap();
+    let json_calendar = Calendar::from_file(&json).unwrap();
+    std::fs::remove_file(&ics).unwrap();
+    std::fs::remove_file(&json).unwrap();
+
+    for day in [25, 26] {
+        let date = NaiveDate::from_ymd_opt(2024, 12, day).unwrap();
+        assert!(!ics_calendar.is_business_day(date));
+        assert!(!json_calendar.is_business_day(date));
+    }
+    let new_year = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+    assert!(!ics_calendar.is_business_day(new_year));
+    assert!(json_calendar.is_business_day(new_year));
+    assert_eq!(
+        json_calendar.next_business_day(NaiveDate::from_ymd_opt(2024, 12, 25).unwrap()),
+        NaiveDate::from_ymd_opt(2024, 12, 27)
+    );
+
+    assert!(Calendar::from_file(dir.join("holidays.csv")).is_err());
+}
+
+#[test]
+fn calendar_recurrence_rules() {
+    let path = std::env::temp_dir().join(format!("tm-rrule-{}.ics", std::process::id()));
+    let load = |events: &[&str]| {
+        let events: Vec<String> = events
+            .iter()
+            .map(|event| format!("BEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\n", event))
+            .collect();
+        std::fs::write(
+            &path,
+            format!("BEGIN:VCALENDAR\r\n{}END:VCALENDAR\r\n", events.concat()),
+        )
+        .unwrap();
+        // weekends count as business days so only holidays are checked
+        let calendar = Calendar::from_file(&path).map(|calendar| calendar.with_weekends(false));
+        std::fs::remove_file(&path).unwrap();
+        calendar
+    };
+    let holiday = |calendar: &Calendar, y, m, d| {
+        !calendar.is_business_day(NaiveDate::from_ymd_opt(y, m, d).unwrap())
+    };
+
+    let calendar = load(&[
+        // endless, spanning two days each year
+        "DTSTART;VALUE=DATE:20241225\r\nDTEND;VALUE=DATE:20241227\r\nRRULE:FREQ=YEARLY",
+        "DTSTART;VALUE=DATE:20240704\r\nRRULE:FREQ=YEARLY;COUNT=2",
+        "DTSTART;VALUE=DATE:20241111\r\nRRULE:FREQ=YEARLY;UNTIL=20251111",
+        "DTSTART;VALUE=DATE:20240501\r\nRRULE:FREQ=YEARLY;INTERVAL=2;BYMONTH=5;BYMONTHDAY=1",
+        // February 29 only exists in leap years
+        "DTSTART;VALUE=DATE:20240229\r\nRRULE:FREQ=YEARLY;COUNT=2",
+    ])
+    .unwrap();
+
+    for year in [2024, 2025, 2030, 2099] {
+        assert!(holiday(&calendar, year, 12, 25) && holiday(&calendar, year, 12, 26));
+    }
+    assert!(!holiday(&calendar, 2024, 12, 27));
+    assert!(holiday(&calendar, 2025, 7, 4) && !holiday(&calendar, 2026, 7, 4));
+    assert!(holiday(&calendar, 2025, 11, 11) && !holiday(&calendar, 2026, 11, 11));
+    assert!(holiday(&calendar, 2026, 5, 1) && !holiday(&calendar, 2025, 5, 1));
+    assert!(holiday(&calendar, 2028, 2, 29) && !holiday(&calendar, 2025, 3, 1));
+    assert!(!holiday(&calendar, 2032, 2, 29));
+
+    for rule in [
+        "FREQ=WEEKLY",
+        "FREQ=MONTHLY;COUNT=3",
+        "FREQ=YEARLY;BYDAY=MO",
+        "FREQ=YEARLY;BYMONTH=6",
+        "FREQ=YEARLY;COUNT=0",
+    ] {
+        let event = format!("DTSTART;VALUE=DATE:20240101\r\nRRULE:{}", rule);
+        assert!(load(&[&event]).is_err(), "{}", rule);
+    }
+}
+
+#[test]
+fn composite_calendar() {
+    let schedule = composite("0 9 * * *")
+        .union(composite("0 18 * * *"))
+        .with_calendar(christmas(), Some("UTC"))
+        .unwrap();
+
+    assert_eq!(
+        composite_next(&schedule, "2024-12-24 19:00:00"),
+        Some(utc_from_str("2024-12-27 09:00:00"))
+    );
+}
+
+#[test]
+fn hash_stable() {
+    let minute = |key: &str| {
+        let expression = Expression::from_str_with_key("H H(9-17) H * *", key).unwrap();
+        let [_, minute, hour, dom, ..] = &expression.fields;
+        let [minute, hour, dom] = [minute, hour, dom].map(|f| f.parse::<i32>().unwrap());
+        assert!((9..=17).contains(&hour));
+        assert!((1..=28).contains(&dom));
+        minute
+    };
+
+    assert_eq!(minute("reports"), minute("reports"));
+    // the same schedule under different names spreads over the hour
+    let minutes: std::collections::HashSet<_> =
+        (0..20).map(|i| minute(&format!("graph {}", i))).collect();
+    assert!(minutes.len() > 10);
+}
+
+#[test]
+fn hash_step() {
+    let expression = Expression::from_str_with_key("H/15 * * * *", "reports").unwrap();
+    let (start, rest) = expression.fields[1].split_once('-').unwrap();
+    let start = start.parse::<u32>().unwrap();
+    assert!(start < 15);
+    assert_eq!(rest, "59/15");
+
+    let next = expression
+        .next(utc_from_str("2024-01-01 00:59:00"))
+        .unwrap();
+    assert_eq!(
+        next,
+        utc_from_str("2024-01-01 01:00:00") + chrono::Duration::minutes(start as _)
+    );
+}
+
+#[test]
+fn hash_errors() {
+    let error = |expression| Expression::from_str(expression).unwrap_err();
+
+    assert!(matches!(
+        error("H(0-99) * * * *"),
+        CronError::OutOfRange { value: 99, .. }
+    ));
+    assert_eq!(error("0 H(0-99) * * *").position().unwrap().offset, 6);
+    assert!(matches!(
+        error("H(5-1) * * * *"),
+        CronError::InvalidPart { .. }
+    ));
+    assert!(matches!(
+        error("H(1-5 * * * *"),
+        CronError::InvalidPart { .. }
+    ));
+    assert!(matches!(
+        error("H-5 * * * *"),
+        CronError::InvalidPart { .. }
+    ));
+}
+
+#[test]
+fn timetable_interval() {
+    let interval =
+        Interval::new(chrono::Duration::minutes(90), utc("2024-01-01 00:15:00")).unwrap();
+    let next = |after| interval.next(utc(after), &LastRun::Never).unwrap();
+
+    assert_eq!(
+        next("2023-12-31 00:00:00"),
+        Some(utc("2024-01-01 00:15:00"))
+    );
+    assert_eq!(
+        next("2024-01-01 03:00:00"),
+        Some(utc("2024-01-01 03:15:00"))
+    );
+    assert_eq!(
+        next("2024-01-01 03:15:00"),
+        Some(utc("2024-01-01 04:45:00"))
+    );
+
+    // sub-second parts would be truncated, zero would divide by zero
+    for (every, reason) in [
+        (Duration::milliseconds(500), "must be at least one second"),
+        (Duration::zero(), "must be at least one second"),
+        (Duration::seconds(-5), "must be at least one second"),
+        (
+            Duration::milliseconds(1500),
+            "must be a whole number of seconds",
+        ),
+    ] {
+        match Interval::new(every, utc("2024-01-01 00:00:00")) {
+            Err(CronError::InvalidInterval { reason: got, .. }) => assert_eq!(got, reason),
+            other => panic!("{:?} gave {:?}", every, other),
+        }
+    }
+    assert_eq!(
+        Interval::new(Duration::milliseconds(1500), utc("2024-01-01 00:00:00"))
+            .unwrap_err()
+            .to_string(),
+        "Interval 1.5s must be a whole number of seconds"
+    );
+}
+
+#[cfg(feature = "python")]
+#[test]
+fn timetable_python_errors() {
+    use super::timetable::PyTimetable;
+    use pyo3::{exceptions::PyValueError, prelude::*};
+
+    const TIMETABLES: &str = "class Flaky:
+    calls = 0
+    def next(self, after):
+        self.calls += 1
+        if self.calls == 1:
+            raise ValueError('flaky')
+        return after
+";
+    Python::with_gil(|py| {
+        let timetables = PyModule::from_code(py, TIMETABLES, "timetables.py", "timetables");
+        let flaky = timetables
+            .unwrap()
+            .getattr("Flaky")
+            .unwrap()
+            .call0()
+            .unwrap();
+        let timetable: PyTimetable = flaky.extract().unwrap();
+        let now = utc("2024-01-01 12:00:00");
+
+        // raised to the executor, which asks again later
+        let e = timetable.next(now, &LastRun::Never).unwrap_err();
+        let e = e.downcast::<PyErr>().unwrap();
+        assert!(e.is_instance_of::<PyValueError>(py), "{}", e);
+        assert_eq!(timetable.next(now, &LastRun::Never).unwrap(), Some(now));
+    });
+}
+
+#[test]
+fn timetable_after_run() {
+    let after_run = AfterRun::new(chrono::Duration::minutes(10));
+    let now = utc("2024-01-01 12:00:00");
+    let started = utc("2024-01-01 11:50:00");
+
+    assert_eq!(after_run.next(now, &LastRun::Never).unwrap(), Some(now));
+    assert_eq!(
+        after_run.next(now, &LastRun::Running { started }).unwrap(),
+        None
+    );
+
+    let finished = utc("2024-01-01 11:55:00");
+    assert_eq!(
+        after_run
+            .next(now, &LastRun::Finished { started, finished })
+            .unwrap(),
+        Some(utc("2024-01-01 12:05:00"))
+    );
+    // overdue runs start right away
+    let finished = utc("2024-01-01 11:00:00");
+    assert_eq!(
+        after_run
+            .next(now, &LastRun::Finished { started, finished })
+            .unwrap(),
+        Some(now)
+    );
+
+    let expression = Expression::from_str("0 13 * * *").unwrap();
+    assert_eq!(
+        Timetable::next(&expression, now, &LastRun::Never).unwrap(),
+        Some(utc("2024-01-01 13:00:00"))
+    );
+}
+
+#[test]
+fn crontab_entries() {
+    let content = r#"
+# m h dom mon dow user command
+SHELL=/bin/bash
+MAILTO = "ops@example.com"
+
+*/15 * * * * root backup.sh --since "$(date +\%F)"
+CRON_TZ=Europe/Berlin
+@daily  nobody  mail -s report ops%line one%line two
+"#;
+    let entries = crontab::parse("crontab", content, true).unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let backup = &entries[0];
+    assert_eq!(backup.name, "crontab:6");
+    assert_eq!(backup.expression.to_string(), "*/15 * * * *");
+    assert_eq!(backup.user.as_deref(), Some("root"));
+    assert_eq!(backup.command, r#"backup.sh --since "$(date +%F)""#);
+    assert_eq!(backup.input, None);
+    assert_eq!(backup.env["SHELL"], "/bin/bash");
+    assert_eq!(backup.env["MAILTO"], "ops@example.com");
+    assert!(!backup.env.contains_key("CRON_TZ"));
+
+    let report = &entries[1];
+    assert_eq!(report.expression.to_string(), "0 0 * * *");
+    assert_eq!(
+        report.expression.next_utc(utc("2024-01-01 12:00:00")),
+        Some(utc("2024-01-01 23:00:00"))
+    );
+    assert_eq!(report.command, "mail -s report ops");
+    assert_eq!(report.input.as_deref(), Some("line one\nline two"));
+
+    // without a user column, the user is part of the command
+    let entries = crontab::parse("crontab", content, false).unwrap();
+    assert_eq!(entries[0].user, None);
+    assert!(entries[0].command.starts_with("root backup.sh"));
+}
+
+#[test]
+fn crontab_errors() {
+    let error = |content| crontab::parse("crontab", content, false).unwrap_err();
+
+    assert_eq!(
+        error("A=1\n\n0 0 1,2,x * * true").to_string(),
+        "Invalid crontab entry crontab:3"
+    );
+    assert!(error("0 0 32 * * true")
+        .downcast_ref::<CronError>()
+        .is_some());
+    assert!(error("@reboot true")
+        .root_cause()
+        .to_string()
+        .contains("@reboot"));
+    assert!(error("0 0 * * *")
+        .root_cause()
+        .to_string()
+        .contains("no command"));
+}
+
+// runs 256 cases, set PROPTEST_CASES for more
+proptest! {
+    #[test]
+    fn next_matches_reference(fields in reference::expressions(), now in reference::times()) {
+        let horizon = Duration::days(HORIZON_DAYS - 1);
+        let expression = Expression::from_str(&fields.to_string())
+            .map_err(anyhow::Error::from)
+            .and_then(|expression| match fields.and {
+                true => expression.with_day_semantics("and"),
+                false => Ok(expression),
+            });
+        let expression = match expression {
+            Ok(expression) => expression,
+            // like Feb 30, the reference must agree
+            Err(e) if matches!(e.downcast_ref(), Some(CronError::NeverFires)) => {
+                prop_assert_eq!(fields.next(now), None, "{}", fields);
+                return Ok(());
+            }
+            Err(e) => return Err(TestCaseError::fail(format!("{}: {}", fields, e))),
+        };
+
+        match fields.next(now) {
+            Some(next) => prop_assert_eq!(expression.next(now), Some(next), "{}", fields),
+            None => prop_assert!(
+                expression.next(now).is_none_or(|next| next > now + horizon),
+                "{}",
+                fields
+            ),
+        }
+        match fields.prev(now) {
+            Some(prev) => prop_assert_eq!(expression.prev(now), Some(prev), "{}", fields),
+            None => prop_assert!(
+                expression.prev(now).is_none_or(|prev| prev < now - horizon),
+                "{}",
+                fields
+            ),
+        }
+    }
+
+    #[test]
+    fn parse_never_panics(expression in prop_oneof![
+        3 => mutated_expressions(),
+        1 => "[0-9A-Za-z*/,#?@: -]{0,30}",
+    ]) {
+        if let Ok(expression) = Expression::from_str(&expression) {
+            evaluate_anywhere(&expression);
+        }
+    }
+
+    #[test]
+    fn every_never_panics(expression in every_expressions()) {
+        if let Ok(expression) = Expression::from_str(&expression) {
+            evaluate_anywhere(&expression);
+        }
+    }
+}
+
+// Reference expressions with up to two characters deleted, replaced or inserted,
+// so that most of them still parse and reach `evaluate_anywhere`
+fn mutated_expressions() -> impl Strategy<Value = String> {
+    let characters = b"0123456789*/,-#?LW ".to_vec();
+    let edit = (
+        any::<prop::sample::Index>(),
+        0..3u8,
+        prop::sample::select(characters),
+    );
+    let edits = prop::collection::vec(edit, 0..=2);
+    (reference::expressions(), edits).prop_map(|(fields, edits)| {
+        let mut expression = fields.to_string().into_bytes();
+        for (at, edit, character) in edits {
+            let at = at.index(expression.len() + 1);
+            match edit {
+                0 if at < expression.len() => {
+                    expression.remove(at);
+                }
+                1 if at < expression.len() => expression[at] = character,
+                _ => expression.insert(at, character),
+            }
+        }
+        String::from_utf8(expression).unwrap()
+    })
+}
+
+// `@every` with one or two parts, small or up to u64::MAX
+fn every_expressions() -> impl Strategy<Value = String> {
+    let part = (prop_oneof![0..100u64, any::<u64>()], "[hms]");
+    prop::collection::vec(part, 1..=2).prop_map(|parts| {
+        let parts: String = parts
+            .iter()
+            .map(|(n, unit)| format!("{}{}", n, unit))
+            .collect();
+        format!("@every {}", parts)
+    })
+}
+
+// Any expression that parses must evaluate, even at the ends of time
+fn evaluate_anywhere(expression: &Expression) {
+    expression.to_string();
+    expression.describe();
+    for now in [
+        utc_from_str("2024-01-01 00:00:00"),
+        NaiveDateTime::MIN,
+        NaiveDateTime::MAX,
+    ] {
+        expression.next(now);
+        expression.prev(now);
+    }
+    for now in [DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC] {
+        expression.next_utc(now);
+        expression.prev_utc(now);
+        expression.matches_utc(now);
+    }
+}
@@ -0,0 +1,223 @@
+use super::composite::Composite;
+use super::error::CronError;
+use super::expression::Expression;
+#[cfg(feature = "python")]
+use super::{expression::Moment, helpers::parse_interval};
+use anyhow::Result;
+#[cfg(feature = "python")]
+use chrono::TimeZone;
+use chrono::{DateTime, Duration, Utc};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// The previous run of a graph, as seen by the executor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LastRun {
+    #[default]
+    Never,
+    Running {
+        started: DateTime<Utc>,
+    },
+    Finished {
+        started: DateTime<Utc>,
+        finished: DateTime<Utc>,
+    },
+}
+
+/// When a graph runs. The executor asks for the next run whenever a run starts
+/// or finishes, so timetables that depend on runs can wait for them
+pub trait Timetable: Send {
+    /// First run after `after`, None once the timetable is exhausted
+    /// or while it waits for the graph to finish. Errors don't end the
+    /// timetable, the executor asks again later
+    fn next(&self, after: DateTime<Utc>, last_run: &LastRun) -> Result<Option<DateTime<Utc>>>;
+}
+
+impl Timetable for Expression {
+    fn next(&self, after: DateTime<Utc>, _: &LastRun) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.next_utc(after))
+    }
+}
+
+impl Timetable for Composite {
+    fn next(&self, after: DateTime<Utc>, _: &LastRun) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.next_utc(after))
+    }
+}
+
+/// A duration as a timedelta or a string like `1h30m`, same as `@every`
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum Every {
+    Str(String),
+    Delta(Duration),
+}
+
+#[cfg(feature = "python")]
+impl Every {
+    fn into_duration(self) -> Result<Duration, CronError> {
+        match self {
+            Every::Str(every) => parse_interval(&every),
+            Every::Delta(every) => whole_seconds(every),
+        }
+    }
+}
+
+// Runs are aligned to whole seconds, a fraction would be silently dropped
+fn whole_seconds(every: Duration) -> Result<Duration, CronError> {
+    let reason = match every {
+        _ if every < Duration::seconds(1) => "must be at least one second",
+        _ if every.subsec_nanos() != 0 => "must be a whole number of seconds",
+        _ => return Ok(every),
+    };
+    let seconds = every.num_seconds() as f64 + f64::from(every.subsec_nanos()) / 1e9;
+    Err(CronError::InvalidInterval {
+        interval: format!("{}s", seconds),
+        reason,
+    })
+}
+
+/// Fixed interval anchored at a start time, unlike `@every` which counts from now
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone, Debug)]
+pub struct Interval {
+    every: Duration,
+    start: DateTime<Utc>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Interval {
+    /// Naive start times are UTC, the default start is the Unix epoch
+    /// so that `Interval("1h")` fires on the hour
+    #[new]
+    #[pyo3(signature = (every, start = None))]
+    fn py_new(every: Every, start: Option<Moment>) -> PyResult<Self> {
+        let start = match start {
+            Some(Moment::Aware(start)) => start.with_timezone(&Utc),
+            Some(Moment::Naive(start)) => Utc.from_utc_datetime(&start),
+            None => DateTime::UNIX_EPOCH,
+        };
+        Ok(Interval::new(every.into_duration()?, start)?)
+    }
+
+    /// Next run after `after`, naive datetimes are treated and returned as UTC
+    #[pyo3(name = "next")]
+    fn py_next(&self, py: Python<'_>, after: Moment) -> PyObject {
+        match after {
+            Moment::Aware(after) => self.next_utc(after.with_timezone(&Utc)).into_py(py),
+            Moment::Naive(after) => self
+                .next_utc(Utc.from_utc_datetime(&after))
+                .naive_utc()
+                .into_py(py),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Interval(\"{}s\", start=datetime.fromisoformat(\"{}\"))",
+            self.every.num_seconds(),
+            self.start.to_rfc3339()
+        )
+    }
+}
+
+impl Interval {
+    /// Fails unless `every` is a whole number of seconds, at least one
+    pub fn new(every: Duration, start: DateTime<Utc>) -> Result<Self, CronError> {
+        Ok(Interval {
+            every: whole_seconds(every)?,
+            start,
+        })
+    }
+
+    /// First `start + n * every` after `after`
+    pub fn next_utc(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        if after < self.start {
+            return self.start;
+        }
+
+        let every = self.every.num_seconds();
+        let elapsed = (after - self.start).num_seconds();
+        self.start + Duration::seconds((elapsed / every + 1) * every)
+    }
+}
+
+impl Timetable for Interval {
+    fn next(&self, after: DateTime<Utc>, _: &LastRun) -> Result<Option<DateTime<Utc>>> {
+        Ok(Some(self.next_utc(after)))
+    }
+}
+
+/// Runs a fixed delay after the previous run finishes, so runs never overlap.
+/// The first run starts right away
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone, Debug)]
+pub struct AfterRun {
+    every: Duration,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl AfterRun {
+    #[new]
+    fn py_new(every: Every) -> PyResult<Self> {
+        Ok(AfterRun::new(every.into_duration()?))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("AfterRun(\"{}s\")", self.every.num_seconds())
+    }
+}
+
+impl AfterRun {
+    pub fn new(every: Duration) -> Self {
+        AfterRun { every }
+    }
+}
+
+impl Timetable for AfterRun {
+    fn next(&self, after: DateTime<Utc>, last_run: &LastRun) -> Result<Option<DateTime<Utc>>> {
+        Ok(match last_run {
+            LastRun::Never => Some(after),
+            LastRun::Running { .. } => None,
+            // a run that finished long ago is due right away
+            LastRun::Finished { finished, .. } => Some((*finished + self.every).max(after)),
+        })
+    }
+}
+
+/// Any Python object with a `next(after)` method that takes an aware UTC datetime
+/// and returns the next run as a datetime, or None to stop. Naive datetimes are UTC
+#[cfg(feature = "python")]
+pub struct PyTimetable(PyObject);
+
+#[cfg(feature = "python")]
+impl<'source> FromPyObject<'source> for PyTimetable {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        match ob.hasattr("next")? {
+            true => Ok(PyTimetable(ob.into())),
+            false => Err(pyo3::exceptions::PyTypeError::new_err(format!(
+                "{} has no next(after) method",
+                ob.repr()?
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+impl Timetable for PyTimetable {
+    fn next(&self, after: DateTime<Utc>, _: &LastRun) -> Result<Option<DateTime<Utc>>> {
+        Python::with_gil(|py| {
+            let next = self
+                .0
+                .call_method1(py, "next", (after,))?
+                .extract::<Option<Moment>>(py)?;
+
+            Ok(next.map(|next| match next {
+                Moment::Aware(next) => next.with_timezone(&Utc),
+                Moment::Naive(next) => Utc.from_utc_datetime(&next),
+            }))
+        })
+    }
+}
@@ -0,0 +1,190 @@
+use super::error::{CronError, Position};
+use super::unit::Unit;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Lexical pieces of a comma-separated part of a field
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    /// A number, or a month/day-of-week name already converted to its number
+    Value(i32),
+    Star,
+    Dash,
+    Slash,
+    Hash,
+    /// `L` of the Quartz-style modifiers
+    Last,
+    /// `W` of the Quartz-style modifiers
+    Weekday,
+    /// Jenkins-style `H` or `H(min-max)`, a value of the range picked by `hash`
+    Hashed {
+        min: i32,
+        max: i32,
+        hash: u64,
+    },
+}
+
+/// Splits a part into tokens, each with its offset within the part.
+/// Names are case-insensitive and only accepted in the fields that have them,
+/// `H` picks its value with `seed`, see `helpers::seed`
+pub fn tokenize(unit: &Unit, part: &str, seed: u64) -> Result<Vec<(usize, Token)>, CronError> {
+    let mut tokens = vec![];
+    let mut rest = part;
+
+    while let Some(c) = rest.chars().next() {
+        let offset = part.len() - rest.len();
+        let invalid = || CronError::InvalidValue {
+            at: Position {
+                field: unit.clone(),
+                part: 0,
+                offset,
+            },
+            value: match rest.find(|c: char| !c.is_ascii_alphanumeric()) {
+                Some(0) => c.to_string(),
+                Some(end) => rest[..end].into(),
+                None => rest.into(),
+            },
+        };
+
+        let (token, len) = match c {
+            '*' => (Token::Star, 1),
+            '-' => (Token::Dash, 1),
+            '/' => (Token::Slash, 1),
+            '#' => (Token::Hash, 1),
+            '0'..='9' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let value = rest[..len].parse().map_err(|_| invalid())?;
+                (Token::Value(value), len)
+            }
+            _ => match (name(unit, rest), c.to_ascii_uppercase()) {
+                (Some(value), _) => (Token::Value(value), 3),
+                (None, 'L') => (Token::Last, 1),
+                (None, 'W') => (Token::Weekday, 1),
+                (None, 'H') => hashed(unit, rest, seed).map_err(|e| e.locate(0, offset))?,
+                (None, _) => return Err(invalid()),
+            },
+        };
+
+        tokens.push((offset, token));
+        rest = &rest[len..];
+    }
+
+    Ok(tokens)
+}
+
+/// Canonical form of tokens, numbers without leading zeros and uppercase modifiers.
+/// `H` is rendered as the values it picked
+pub fn render(tokens: &[(usize, Token)]) -> String {
+    match *tokens {
+        [(_, Token::Hashed { min, max, hash })] => return pick(min, max, hash, None).to_string(),
+        [(_, Token::Hashed { min, max, hash }), (_, Token::Slash), (_, Token::Value(step))]
+            if step > 0 =>
+        {
+            let start = pick(min, max, hash, Some(step as usize));
+            return format!("{}-{}/{}", start, max, step);
+        }
+        _ => (),
+    }
+
+    tokens
+        .iter()
+        .map(|(_, token)| match token {
+            Token::Value(value) => value.to_string(),
+            Token::Star => "*".into(),
+            Token::Dash => "-".into(),
+            Token::Slash => "/".into(),
+            Token::Hash => "#".into(),
+            Token::Last => "L".into(),
+            Token::Weekday => "W".into(),
+            Token::Hashed { .. } => "H".into(),
+        })
+        .collect()
+}
+
+/// Value of `H` within `min..=max`, or the first one of `H/step`
+/// which must be before the step repeats
+pub fn pick(min: i32, max: i32, hash: u64, step: Option<usize>) -> i32 {
+    let span = (max - min + 1) as u64;
+    let span = step.map_or(span, |step| span.min(step as u64));
+    min + (hash % span) as i32
+}
+
+// `H` or `H(min-max)` at the start of `rest`, and its length.
+// Days of the month are picked up to the 28th so every month has them
+fn hashed(unit: &Unit, rest: &str, seed: u64) -> Result<(Token, usize), CronError> {
+    // every field of an expression picks a different value
+    let field = unit.clone() as u64 + 1;
+    let hash = mix(seed.wrapping_add(field.wrapping_mul(0x9e3779b97f4a7c15)));
+    let (min, max) = match unit {
+        Unit::Dom => (1, 28),
+        Unit::Dow => (0, 6),
+        _ => unit.bounds(),
+    };
+
+    let Some(range) = rest[1..].strip_prefix('(') else {
+        return Ok((Token::Hashed { min, max, hash }, 1));
+    };
+
+    let invalid = || CronError::InvalidPart {
+        at: Position {
+            field: unit.clone(),
+            part: 0,
+            offset: 0,
+        },
+        part: rest.into(),
+    };
+    let end = range.find(')').ok_or_else(invalid)?;
+    let tokens = tokenize(unit, &range[..end], seed).map_err(|e| e.locate(0, 2))?;
+
+    let [(o_min, Token::Value(min)), (_, Token::Dash), (o_max, Token::Value(max))] = tokens[..]
+    else {
+        return Err(invalid());
+    };
+    let (lower, upper) = unit.bounds();
+    for (offset, value) in [(o_min, min), (o_max, max)] {
+        if !(lower..=upper).contains(&value) {
+            return Err(CronError::OutOfRange {
+                at: Position {
+                    field: unit.clone(),
+                    part: 0,
+                    offset: offset + 2,
+                },
+                value,
+                min: lower,
+                max: upper,
+            });
+        }
+    }
+    if min > max {
+        return Err(invalid());
+    }
+
+    Ok((Token::Hashed { min, max, hash }, end + 3))
+}
+
+// splitmix64 finalizer, spreads nearby seeds over the whole range
+fn mix(mut hash: u64) -> u64 {
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+// value of the three-letter name `rest` starts with
+fn name(unit: &Unit, rest: &str) -> Option<i32> {
+    let (names, first): (&[&str], i32) = match unit {
+        Unit::Month => (&MONTHS, 1),
+        Unit::Dow => (&WEEKDAYS, 0),
+        _ => return None,
+    };
+
+    let word = rest.get(..3)?;
+    names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(word))
+        .map(|index| index as i32 + first)
+}
@@ -1,179 +1,209 @@
-use anyhow::{anyhow, Error};
-use chrono::{Datelike, NaiveDateTime, Timelike};
-use itertools::Itertools;
-use std::collections::HashMap;
+use super::error::{CronError, Position};
+use super::token::{pick, render, tokenize, Token};
+use std::fmt;
 
+/// Fields of an expression
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
 pub enum Unit {
+    Second,
     Minute,
     Dow,
     Hour,
     Dom,
     Month,
-
-    Day,
     Year,
-    None,
+}
+
+/// Quartz-style day modifiers that depend on the month being evaluated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Modifier {
+    /// `L` and `L-3`, days before the last day of the month
+    LastDay(i32),
+    /// `LW`, last weekday (Mon-Fri) of the month
+    LastWeekday,
+    /// `15W`, weekday nearest to the given day, without leaving the month
+    NearestWeekday(i32),
+    /// `5L`, last given day of week in the month
+    LastDow(i32),
+    /// `2#3`, nth given day of week in the month
+    NthDow(i32, i32),
+}
+
+/// A comma-separated part of a field
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Part {
+    Values(Vec<i32>),
+    Modifier(Modifier),
 }
 
 impl Unit {
-    pub fn range_from_str(&self, v: &str) -> Result<Vec<i32>, Error> {
-        let (mut start, mut end) = match self {
-            Unit::Minute => (0, 59),
-            Unit::Hour => (0, 23),
-            Unit::Dom => (1, 31),
-            Unit::Dow => (0, 6),
-            Unit::Month => (1, 12),
-            _ => unreachable!(),
+    /// Parses a part following the Vixie cron grammar, `*`, `5`, `1-5`, `*/2`, `5/15`, `1-5/2`,
+    /// plus the Quartz-style modifiers. Ranges wrap around when start is after end, like `fri-mon`,
+    /// and both 0 and 7 are Sunday. Jenkins-style `H`, `H(0-29)` and `H/15` pick their values with `seed`
+    pub fn parse_part(&self, part: &str, seed: u64) -> Result<Part, CronError> {
+        use Token::*;
+
+        let tokens = tokenize(self, part, seed)?;
+        let (min, max) = self.bounds();
+
+        let invalid = |offset| CronError::InvalidPart {
+            at: self.at(offset),
+            part: part.into(),
         };
+        let bounded = |&(offset, token): &(usize, Token), (min, max)| match token {
+            Value(value) if (min..=max).contains(&value) => Ok(value),
+            Value(value) => Err(CronError::OutOfRange {
+                at: self.at(offset),
+                value,
+                min,
+                max,
+            }),
+            _ => Err(invalid(offset)),
+        };
+        let value = |token| bounded(token, (min, max));
+        let step = |&(offset, token): &(usize, Token)| match token {
+            Value(step) if step > 0 => Ok(step as usize),
+            Value(step) => Err(CronError::InvalidStep {
+                at: self.at(offset),
+                step: step.to_string(),
+            }),
+            _ => Err(invalid(offset)),
+        };
+        let modifier = |modifier| Ok(Part::Modifier(modifier));
 
-        if v == "*" {
-            return Ok((start..=end).collect());
-        }
-
-        if v.contains('/') {
-            let step = v.split_once('/').unwrap().1.parse()?;
-            let r = v.split_once('/').unwrap().0;
-            if r.contains('-') {
-                let (l, r) = v.split_once('/').unwrap().0.split_once('-').unwrap();
-                let (start, end) = self.validate_range(l.parse()?, r.parse()?)?;
-                return Ok((start..=end).step_by(step).collect());
-            } else if r == "*" {
-                return Ok((start..=end).step_by(step).collect());
-            } else {
-                return Err(anyhow!(
-                    "Intervals must be used with a range (* or an explicit range)"
-                ));
+        match (self, tokens.as_slice()) {
+            (Unit::Dom, [(_, Last)]) => modifier(Modifier::LastDay(0)),
+            (Unit::Dom, [(_, Last), (_, Weekday)]) => modifier(Modifier::LastWeekday),
+            (Unit::Dom, [(_, Last), (_, Dash), offset]) => {
+                modifier(Modifier::LastDay(bounded(offset, (0, 30))?))
             }
+            (Unit::Dom, [day, (_, Weekday)]) => modifier(Modifier::NearestWeekday(value(day)?)),
+            (Unit::Dow, [dow, (_, Last)]) => modifier(Modifier::LastDow(value(dow)? % 7)),
+            (Unit::Dow, [dow, (_, Hash), nth]) => {
+                modifier(Modifier::NthDow(value(dow)? % 7, bounded(nth, (1, 5))?))
+            }
+            (_, [(_, Hashed { min, max, hash })]) => {
+                let value = pick(*min, *max, *hash, None);
+                Ok(self.range(value, value, 1))
+            }
+            (_, [(_, Hashed { min, max, hash }), (_, Slash), by]) => {
+                let step = step(by)?;
+                Ok(self.range(pick(*min, *max, *hash, Some(step)), *max, step))
+            }
+            (_, [(_, Star)]) => Ok(self.range(min, max, 1)),
+            (_, [(_, Star), (_, Slash), by]) => Ok(self.range(min, max, step(by)?)),
+            (_, [start]) => {
+                let start = value(start)?;
+                Ok(self.range(start, start, 1))
+            }
+            (_, [start, (_, Slash), by]) => Ok(self.range(value(start)?, max, step(by)?)),
+            (_, [start, (_, Dash), end]) => Ok(self.range(value(start)?, value(end)?, 1)),
+            (_, [start, (_, Dash), end, (_, Slash), by]) => {
+                Ok(self.range(value(start)?, value(end)?, step(by)?))
+            }
+            (_, [(offset, _), ..]) => Err(invalid(*offset)),
+            (_, []) => Err(invalid(0)),
         }
-
-        if !v.contains('/') && v.contains('-') {
-            let (l, r) = v.split_once('-').unwrap();
-            (start, end) = self.validate_range(l.parse()?, r.parse()?)?;
-            return Ok((start..=end).collect());
-        }
-
-        if let Ok(num) = v.parse() {
-            (start, end) = self.validate_range(num, num)?;
-            return Ok((start..=end).collect());
-        }
-
-        Err(anyhow!("Value {} is not valid in field {:?}", v, self))
-    }
-
-    pub fn next(&self) -> Self {
-        match self {
-            Unit::Year => Unit::Month,
-            Unit::Month => Unit::Day,
-            Unit::Day => Unit::Hour,
-            Unit::Hour => Unit::Minute,
-            Unit::Minute => Unit::None,
-            _ => unreachable!(),
-        }
-    }
-
-    pub fn set(&self, mut time: HashMap<Unit, i32>, value: i32) -> HashMap<Unit, i32> {
-        time.insert(self.clone(), value);
-        time
     }
 
-    pub fn to_hash(time: NaiveDateTime) -> HashMap<Unit, i32> {
-        HashMap::from([
-            (Unit::Year, Unit::Year.get(time)),
-            (Unit::Month, Unit::Month.get(time)),
-            (Unit::Day, Unit::Day.get(time)),
-            (Unit::Hour, Unit::Hour.get(time)),
-            (Unit::Minute, Unit::Minute.get(time)),
-        ])
+    /// Canonical form of valid fields, see `canonical`
+    pub fn canonical_fields(fields: &[String; 7], seed: u64) -> [String; 7] {
+        let units = [
+            Unit::Second,
+            Unit::Minute,
+            Unit::Hour,
+            Unit::Dom,
+            Unit::Month,
+            Unit::Dow,
+            Unit::Year,
+        ];
+        units
+            .iter()
+            .zip(fields)
+            .map(|(unit, field)| unit.canonical(field, seed))
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap()
     }
 
-    pub fn from_hash(hash: HashMap<Unit, i32>) -> NaiveDateTime {
-        let year = hash[&Unit::Year];
-        let month = hash[&Unit::Month];
-        let day = hash[&Unit::Day];
-        let hour = hash[&Unit::Hour];
-        let min = hash[&Unit::Minute];
-        NaiveDateTime::parse_from_str(
-            &format!("{year}-{month}-{day} {hour}:{min}:00"),
-            "%Y-%m-%d %H:%M:%S",
-        )
-        .unwrap()
+    /// Canonical form of a valid field, with names converted to numbers
+    /// and `H` to the values it picked
+    pub fn canonical(&self, field: &str, seed: u64) -> String {
+        field
+            .split(',')
+            .map(|part| match tokenize(self, part, seed) {
+                Ok(tokens) => render(&tokens),
+                Err(_) => part.into(),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
     }
+}
 
-    pub fn parse_to_numeric(fields: [String; 5]) -> [String; 5] {
-        fields
-            .iter()
-            .enumerate()
-            .map(|(index, field)| match index {
-                3 => Unit::Month.to_num(field),
-                4 => Unit::Dow.to_num(field),
-                _ => field.to_string(),
-            })
-            .collect_vec()
-            .try_into()
-            .unwrap()
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Unit::Second => "second",
+            Unit::Minute => "minute",
+            Unit::Hour => "hour",
+            Unit::Dom => "day of month",
+            Unit::Month => "month",
+            Unit::Dow => "day of week",
+            Unit::Year => "year",
+        };
+        write!(f, "{}", name)
     }
 }
 
 impl Unit {
-    fn validate_range(&self, start: i32, end: i32) -> Result<(i32, i32), Error> {
-        let (i, j) = match self {
+    pub(super) fn bounds(&self) -> (i32, i32) {
+        match self {
+            Unit::Second => (0, 59),
             Unit::Minute => (0, 59),
             Unit::Hour => (0, 23),
             Unit::Dom => (1, 31),
-            Unit::Dow => (0, 6),
+            // both 0 and 7 are Sunday
+            Unit::Dow => (0, 7),
             Unit::Month => (1, 12),
-            _ => unreachable!(),
-        };
-
-        if start < i || end > j {
-            return Err(anyhow!(
-                "Value for {:?} must be between {} and {}",
-                self,
-                start,
-                end
-            ));
+            // same as Quartz
+            Unit::Year => (1970, 2099),
         }
-        Ok((start, end))
     }
 
-    fn get(&self, time: NaiveDateTime) -> i32 {
-        match self {
-            Unit::Year => time.year(),
-            Unit::Month => time.month() as _,
-            Unit::Day => time.day() as _,
-            Unit::Hour => time.hour() as _,
-            Unit::Minute => time.minute() as _,
-            _ => unreachable!(),
+    // offsets are relative to the start of the part, see `CronError::locate`
+    fn at(&self, offset: usize) -> Position {
+        Position {
+            field: self.clone(),
+            part: 0,
+            offset,
         }
     }
 
-    fn to_num(&self, value: &str) -> String {
+    fn range(&self, start: i32, end: i32, step: usize) -> Part {
+        let (min, max) = match self {
+            // wrapping ranges go around the week once, from Saturday to Sunday
+            Unit::Dow => (0, 6),
+            _ => self.bounds(),
+        };
+
+        // a range starting on Sunday 7 continues with Monday 1
+        let (start, end) = match (self, start, end) {
+            (Unit::Dow, 7, 7) => (0, 0),
+            (Unit::Dow, 7, end) => (0, end),
+            _ => (start, end),
+        };
+
+        let values = match start <= end {
+            true => (start..=end).step_by(step).collect(),
+            false => (start..=max)
+                .chain(min..=end)
+                .step_by(step)
+                .collect::<Vec<_>>(),
+        };
+
         match self {
-            Unit::Dow => value
-                .to_lowercase()
-                .replace("sun", "0")
-                .replace("mon", "1")
-                .replace("tue", "2")
-                .replace("wed", "3")
-                .replace("thu", "4")
-                .replace("fri", "5")
-                .replace("sat", "6"),
-            Unit::Month => value
-                .to_lowercase()
-                .replace("jan", "1")
-                .replace("feb", "2")
-                .replace("mar", "3")
-                .replace("apr", "4")
-                .replace("may", "5")
-                .replace("jun", "6")
-                .replace("jul", "7")
-                .replace("aug", "8")
-                .replace("sep", "9")
-                .replace("oct", "10")
-                .replace("nov", "11")
-                .replace("dec", "12"),
-            _ => unreachable!(),
+            Unit::Dow => Part::Values(values.into_iter().map(|dow| dow % 7).collect()),
+            _ => Part::Values(values),
         }
     }
 }
@@ -0,0 +1,80 @@
+use super::{
+    graph::Graph,
+    task::{Message, Task},
+};
+use crate::cron::{
+    crontab::{self, Entry},
+    error::to_py_err,
+};
+use anyhow::{anyhow, Context, Result};
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+use std::io::Write;
+use std::process::{self, Stdio};
+
+/// Runs the command of a crontab entry, fails if it exits with an error
+#[pyclass]
+pub struct Command {
+    entry: Entry,
+}
+
+#[pymethods]
+impl Command {
+    // called like any task, with the config or the outputs of its parents
+    #[pyo3(signature = (*_args, **_kwargs))]
+    fn __call__(
+        &self,
+        py: Python<'_>,
+        _args: &PyTuple,
+        _kwargs: Option<&PyDict>,
+    ) -> Result<Message> {
+        let entry = &self.entry;
+        let shell = entry.env.get("SHELL").map_or("/bin/sh", |shell| shell);
+
+        let mut child = process::Command::new(shell)
+            .arg("-c")
+            .arg(&entry.command)
+            .envs(&entry.env)
+            .stdin(match entry.input {
+                Some(_) => Stdio::piped(),
+                None => Stdio::null(),
+            })
+            .spawn()
+            .with_context(|| format!("Can't run {}", entry.command))?;
+
+        if let (Some(input), Some(mut stdin)) = (&entry.input, child.stdin.take()) {
+            stdin.write_all(input.as_bytes())?;
+        }
+
+        // other tasks of the graph run while the command does
+        let status = py.allow_threads(|| child.wait())?;
+        match status.success() {
+            true => Ok(None),
+            false => Err(anyhow!("{} exited with {}", entry.command, status)),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Command(\"{}\")", self.entry.command)
+    }
+}
+
+/// One graph per entry of a crontab file, named `<file name>:<line number>` with a
+/// single `command` task. Commands run as the executor's user, whatever the user column
+#[pyfunction]
+#[pyo3(signature = (path, user_column = false))]
+pub fn from_crontab(py: Python<'_>, path: &str, user_column: bool) -> PyResult<Vec<Graph>> {
+    crontab::from_file(path, user_column)
+        .map_err(to_py_err)?
+        .into_iter()
+        .map(|entry| {
+            let name = entry.name.clone();
+            let expression = Box::new(entry.expression.clone());
+            let command = Py::new(py, Command { entry })?;
+            let task = Task::new("command", command.into_py(py))?;
+            Ok(Graph::with_task(name, expression, task)?)
+        })
+        .collect()
+}
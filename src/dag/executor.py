@@ -4,6 +4,12 @@ import signal
 from multiprocessing import Process
 from datetime import datetime, timezone
 
+# How often a waiting executor checks for finished runs
+POLL_SECONDS = 1
+
+# How long a graph whose timetable raised waits before it's asked again
+RETRY_SECONDS = 60
+
 # When PyO3 executes Python code, it has to 
 # acquire the GIL. PyO3 support for sub-interpreters is
 # still under development. For now, we use 
@@ -13,6 +19,9 @@ from datetime import datetime, timezone
 class Executor:
     def __init__(self, graphs = []):
         self.graphs = []
+        # (monotonic time, graph) of the graphs to ask again for their next run
+        self.retries = []
+        # (graph, process) of the running graphs
         self.active_handlers = []
         self.pid = os.getpid()
         self.caught = False
@@ -28,7 +37,27 @@ class Executor:
         self.schedule(graph)
 
     def schedule(self, graph):
-        next = graph.next()
+        # already waiting for its next run
+        if any(graph in graphs for (_, graphs) in self.graphs):
+            return
+        if any(g is graph for (_, g) in self.retries):
+            return
+
+        # a failing timetable may recover, keep the graph
+        try:
+            next = graph.next()
+        except Exception as e:
+            print(f"Graph {graph.name()} failed to get its next run, retrying in {RETRY_SECONDS}s: {e!r}")
+            self.retries.append((time.monotonic() + RETRY_SECONDS, graph))
+            return
+
+        # Schedules with a year field or a one-shot `at:` run out eventually,
+        # other timetables wait for the running graph and are asked again once it finishes
+        if next is None:
+            if not self.is_running(graph):
+                print(f"Graph {graph.name()} has no upcoming runs, dropping it")
+            return
+
         for (idx,(dt, graphs)) in enumerate(self.graphs):
             if dt == next:
                 graphs.append(graph)
@@ -49,33 +78,63 @@ class Executor:
 
         if self.caught:
             print("\nForcing shutdown ..")
-            [handler.kill() for handler in self.active_handlers]
+            [handler.kill() for (_, handler) in self.active_handlers]
             exit(1)
 
         self.caught = True
         print("\nCaught an interrupt signal, waiting for graphs to finish")
-        while any([handler.is_alive() for handler in self.active_handlers]):
+        while any([handler.is_alive() for (_, handler) in self.active_handlers]):
             time.sleep(2)
 
         exit(0)
 
+    def is_running(self, graph):
+        return any(g is graph for (g, _) in self.active_handlers)
+
+    # Lets the graphs of finished runs know, and asks them for their next run
+    def reap(self):
+        finished = [(g, h) for (g, h) in self.active_handlers if not h.is_alive()]
+        self.active_handlers = [(g, h) for (g, h) in self.active_handlers if h.is_alive()]
+
+        for (graph, _) in finished:
+            graph.run_finished()
+            self.schedule(graph)
+
+
+    # Asks the graphs whose timetables raised again once their wait is over
+    def retry(self):
+        now = time.monotonic()
+        due = [graph for (at, graph) in self.retries if at <= now]
+        self.retries = [(at, graph) for (at, graph) in self.retries if at > now]
+
+        for graph in due:
+            self.schedule(graph)
 
     def start(self):
-        while True:
-            (next, graphs) = self.graphs.pop()
+        while self.graphs or self.active_handlers or self.retries:
+            self.reap()
+            self.retry()
+
+            if not self.graphs:
+                time.sleep(POLL_SECONDS)
+                continue
+
+            (next, graphs) = self.graphs[-1]
 
             now = datetime.now(timezone.utc)
             next = next.replace(tzinfo=timezone.utc)
-            delta = next - now 
+            delta = (next - now).total_seconds()
 
-            time.sleep(max(0, delta.total_seconds()))
-            # time.sleep(5)
+            # wake up early if a run finishes, its graph may want to run sooner
+            if delta > 0:
+                time.sleep(min(delta, POLL_SECONDS))
+                continue
 
+            self.graphs.pop()
             handlers = [Process(target=graph) for graph in graphs]
+            self.active_handlers += list(zip(graphs, handlers))
 
+            [graph.run_started() for graph in graphs]
             [self.schedule(graph) for graph in graphs]
             [handler.start() for handler in handlers]
 
-            self.active_handlers += handlers
-            self.active_handlers = list(filter(lambda h: h.is_alive(), self.active_handlers))
-
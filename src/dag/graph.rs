@@ -1,30 +1,103 @@
 use super::{
     config_loader::ConfigLoader,
-    task::{Message, Task},
+    runner::Dag,
+    task::{parse_timeout, Message, Task},
+    trigger::TaskState,
 };
-use crate::{cron::expression::Expression, store};
-use anyhow::{anyhow, Error, Result};
+use crate::{
+    cron::{
+        calendar::Calendar,
+        composite::{Composite, Operand},
+        error::CronError,
+        expression::Expression,
+        timetable::{AfterRun, Interval, LastRun, PyTimetable, Timetable},
+    },
+    store,
+};
+use anyhow::{anyhow, Result};
 use chrono::{NaiveDateTime, Utc};
-use itertools::Itertools;
+use pyo3::exceptions::PyTimeoutError;
 use pyo3::types::PyDict;
 use pyo3::{prelude::*, types::PyTuple};
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
 
 #[pyclass]
 pub struct Graph {
     name: String,
-    graph: HashMap<String, Vec<String>>,
-    tasks: HashMap<String, Task>,
-    expression: Option<Expression>,
+    dag: Dag<Task>,
+    // None for manual graphs
+    timetable: Option<Box<dyn Timetable>>,
+    last_run: LastRun,
     cfg_loader: ConfigLoader,
-    execution_order: Vec<String>,
+    // of the last run
+    states: HashMap<String, TaskState>,
     store: store::client::Client,
 }
 
+/// A cron expression, `manual`, a list of expressions that fires when any of them
+/// fires, a schedule combined with `|`, `&` and `-`, or any other timetable,
+/// including Python objects with a `next(after)` method
+// only lives while the graph is built
+#[allow(clippy::large_enum_variant)]
+#[derive(FromPyObject)]
+pub enum ScheduleArg {
+    Str(String),
+    List(Vec<Operand>),
+    Schedule(Operand),
+    Interval(Interval),
+    AfterRun(AfterRun),
+    Timetable(PyTimetable),
+}
+
+impl Graph {
+    /// A graph of a single task, for graphs built in Rust like crontab entries
+    pub fn with_task(name: String, timetable: Box<dyn Timetable>, task: Task) -> Result<Self> {
+        let mut dag = Dag::new();
+        dag.add_task(&task.name.clone(), task.trigger_rule(), task);
+        Ok(Graph {
+            name,
+            dag,
+            timetable: Some(timetable),
+            last_run: LastRun::Never,
+            cfg_loader: ConfigLoader::new("/".into(), None)?,
+            states: HashMap::new(),
+            store: store::client::Client::new()?,
+        })
+    }
+}
+
+// The error of runs that took longer than the graph's timeout
+#[derive(Debug)]
+struct TimedOut(Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out after {}s", self.0.as_secs_f64())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
 #[pymethods]
 impl Graph {
     #[new]
-    fn new(name: String, schedule: &str, config: Option<&str>) -> Result<Self, Error> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        name: String,
+        schedule: ScheduleArg,
+        config: Option<&str>,
+        timezone: Option<&str>,
+        day_semantics: Option<&str>,
+        calendar: Option<Calendar>,
+        max_parallel_tasks: Option<usize>,
+        timeout: Option<f64>,
+    ) -> PyResult<Self> {
+        let dag = Dag::new()
+            .with_max_parallel_tasks(max_parallel_tasks)?
+            .with_timeout(parse_timeout(timeout)?);
+
         let py_file = if config.is_some() {
             Python::with_gil(|py| -> Result<String> {
                 let locals = PyDict::new(py);
@@ -36,19 +109,63 @@ impl Graph {
             "/".into()
         };
 
-        let expression = if schedule.to_lowercase() == "manual" {
-            None
-        } else {
-            Some(Expression::from_str(schedule)?)
+        // options only apply to expressions given as strings
+        let parse = |operand| -> PyResult<Composite> {
+            let Operand::Str(expression) = operand else {
+                return Ok(operand.into_composite()?);
+            };
+
+            // `H` spreads graphs with the same schedule by their names
+            let mut expression = Expression::from_str_with_key(&expression, &name)?;
+            if let Some(timezone) = timezone {
+                expression = expression.with_timezone(timezone)?;
+            }
+            if let Some(day_semantics) = day_semantics {
+                expression = expression.with_day_semantics(day_semantics)?;
+            }
+            Ok(expression.into())
+        };
+
+        // the calendar applies to the whole schedule, with days in the graph's timezone
+        let with_calendar = |cron: Composite| match calendar.clone() {
+            Some(calendar) => cron.with_calendar(calendar, timezone),
+            None => Ok(cron),
+        };
+
+        let timetable: Option<Box<dyn Timetable>> = match schedule {
+            ScheduleArg::Str(schedule) if schedule.to_lowercase() == "manual" => {
+                if calendar.is_some() {
+                    return Err(anyhow!("Calendars only apply to cron schedules").into());
+                }
+                None
+            }
+            ScheduleArg::Str(schedule) => {
+                Some(Box::new(with_calendar(parse(Operand::Str(schedule))?)?))
+            }
+            ScheduleArg::Schedule(operand) => Some(Box::new(with_calendar(parse(operand)?)?)),
+            ScheduleArg::List(operands) => {
+                let mut operands = operands.into_iter();
+                let first = operands.next().ok_or(CronError::EmptySchedule)?;
+                let union = operands.try_fold(parse(first)?, |schedule, operand| {
+                    Ok::<_, PyErr>(schedule.union(parse(operand)?))
+                })?;
+                Some(Box::new(with_calendar(union)?))
+            }
+            _ if calendar.is_some() => {
+                return Err(anyhow!("Calendars only apply to cron schedules").into());
+            }
+            ScheduleArg::Interval(interval) => Some(Box::new(interval)),
+            ScheduleArg::AfterRun(after_run) => Some(Box::new(after_run)),
+            ScheduleArg::Timetable(timetable) => Some(Box::new(timetable)),
         };
 
         Ok(Graph {
             name,
-            expression,
+            dag,
+            timetable,
+            last_run: LastRun::Never,
             cfg_loader: ConfigLoader::new(py_file, config)?,
-            graph: HashMap::new(),
-            tasks: HashMap::new(),
-            execution_order: Vec::new(),
+            states: HashMap::new(),
             store: store::client::Client::new()?,
         })
     }
@@ -58,111 +175,78 @@ impl Graph {
     }
 
     fn add_edges(&mut self, parents: Vec<Task>, children: Option<Vec<Task>>) -> Result<()> {
-        let mut children = children.unwrap_or_default();
+        let children = children.unwrap_or_default();
 
         for parent in parents.iter() {
-            self.graph
-                .entry(parent.name.clone())
-                .or_default()
-                .extend(children.iter().map(|c| c.name.clone()));
-
-            for child in children.iter_mut() {
-                child.add_dep(&parent.name);
+            for child in children.iter() {
+                self.dag.add_edge(&parent.name, &child.name);
             }
         }
 
-        children.iter().for_each(|child| {
-            self.tasks
-                .entry(child.name.clone())
-                .or_insert(child.clone());
-        });
-
-        parents.iter().for_each(|child| {
-            self.tasks
-                .entry(child.name.clone())
-                .or_insert(child.clone());
-        });
+        for task in children.into_iter().chain(parents) {
+            self.dag
+                .add_task(&task.name.clone(), task.trigger_rule(), task);
+        }
 
         Ok(())
     }
 
-    fn next(&self) -> Option<NaiveDateTime> {
-        self.expression
-            .as_ref()
-            .map(|exp| exp.next(Utc::now().naive_utc()))
+    /// None for manual graphs, exhausted timetables and timetables waiting
+    /// for the running graph, see `Timetable`. Raises what Python timetables raise
+    fn next(&self) -> PyResult<Option<NaiveDateTime>> {
+        let Some(timetable) = &self.timetable else {
+            return Ok(None);
+        };
+        let next = timetable.next(Utc::now(), &self.last_run)?;
+        Ok(next.map(|next| next.naive_utc()))
+    }
+
+    /// Called by the executor, runs happen in other processes
+    fn run_started(&mut self) {
+        self.last_run = LastRun::Running {
+            started: Utc::now(),
+        };
+    }
+
+    fn run_finished(&mut self) {
+        let started = match self.last_run {
+            LastRun::Running { started } => started,
+            _ => Utc::now(),
+        };
+        self.last_run = LastRun::Finished {
+            started,
+            finished: Utc::now(),
+        };
     }
 
     fn is_empty(&self) -> bool {
-        self.tasks.is_empty()
+        self.dag.is_empty()
     }
 
     fn is_manual(&self) -> bool {
-        self.expression.is_none()
+        self.timetable.is_none()
     }
 
     fn sort(&self) -> Result<Vec<String>> {
-        let mut sorted = vec![];
-        let mut in_degrees: HashMap<String, usize> = self
-            .tasks
-            .values()
-            .map(|task| (task.name.clone(), task.deps.len()))
-            .collect();
-
-        let mut queue: VecDeque<_> = in_degrees
-            .iter()
-            .filter(|(_, v)| **v == 0)
-            .map(|(k, _)| k)
-            .cloned()
-            .collect();
-
-        queue.iter().for_each(|task| {
-            in_degrees.remove(task);
-        });
-
-        loop {
-            if queue.is_empty() && in_degrees.is_empty() {
-                return Ok(sorted);
-            }
-
-            if queue.is_empty() && !in_degrees.is_empty() {
-                return Err(anyhow!("Graph has a cycle"));
-            }
-
-            let task = queue.pop_front().unwrap();
-
-            sorted.push(task.clone());
-
-            self.graph
-                .get(&task)
-                .unwrap_or(&vec![])
-                .iter()
-                .for_each(|child| {
-                    in_degrees.entry(child.clone()).and_modify(|v| *v -= 1);
-                });
-
-            let next = in_degrees
-                .iter()
-                .filter(|(_, v)| **v == 0)
-                .map(|(k, _)| k)
-                .cloned()
-                .collect_vec();
-
-            next.iter().for_each(|task| {
-                in_degrees.remove(task).unwrap();
-            });
-
-            queue.extend(next);
-        }
+        self.dag.topology().sort()
     }
 
     fn is_sorted(&self) -> bool {
-        !self.execution_order.is_empty()
+        !self.dag.execution_order().is_empty()
     }
 
     fn name(&self) -> String {
         self.name.clone()
     }
 
+    /// State of every task in the last run, like "success" or "upstream_failed"
+    fn states(&self) -> HashMap<String, &'static str> {
+        self.states
+            .iter()
+            .map(|(task, state)| (task.clone(), state.as_str()))
+            .collect()
+    }
+
     #[pyo3(signature=(*args, **kwargs))]
     fn __call__(
         &mut self,
@@ -176,6 +260,11 @@ impl Graph {
                 self.store.update_log(run_id, store::Status::Completed)?;
                 Ok(msg)
             }
+            Err(e) if e.is::<TimedOut>() => {
+                eprintln!("Graph {} {}", &self.name, e);
+                self.store.update_log(run_id, store::Status::TimedOut)?;
+                Err(PyTimeoutError::new_err(format!("Graph {} {}", &self.name, e)).into())
+            }
             Err(e) => {
                 eprintln!("Graph {} failed: {}", &self.name, e);
                 self.store.update_log(run_id, store::Status::Failed)?;
@@ -185,43 +274,48 @@ impl Graph {
     }
 
     fn run(&mut self, py: Python, args: &PyTuple, mut kwargs: Message) -> Result<Message> {
-        if self.is_empty() {
-            return Err(anyhow!("Graph is empty"));
-        }
-
-        if !self.is_sorted() {
-            self.execution_order = self.sort()?;
-        }
-
         if kwargs.is_none() {
             kwargs = self.cfg_loader.load()?;
         }
 
-        let mut output = None;
-
-        for task_name in self.execution_order.iter() {
-            let task = self.tasks.get_mut(task_name).unwrap();
-
-            if task.deps.is_empty() {
-                // root nodes
-                output = task.start(py, args, kwargs.clone())?;
-            } else {
-                // leaf and inner nodes
-                output = task.start(py, PyTuple::empty(py), None)?;
+        // tasks run in other threads, which take the GIL when they call Python
+        let args: Py<PyTuple> = args.into();
+        let mut run = py.allow_threads(|| {
+            self.dag.run(|_, task, inputs| {
+                let mut task = task.clone();
+                let (args, kwargs) = (args.clone(), kwargs.clone());
+                let root = inputs.is_empty();
+                // parents that failed or didn't run pass None
+                for (parent, output) in inputs {
+                    task.set_argument(parent, &output.flatten());
+                }
+                move || {
+                    Python::with_gil(|py| match root {
+                        true => task.start(py, args.as_ref(py), kwargs),
+                        false => task.start(py, PyTuple::empty(py), None),
+                    })
+                }
+            })
+        })?;
+
+        for (task, e) in run.errors.iter() {
+            eprintln!("Task {} failed: {}", task, e);
+        }
+        self.states = run.states.clone();
+
+        match run.status() {
+            store::Status::TimedOut => Err(TimedOut(self.dag.timeout().unwrap()).into()),
+            // the first failure, the others were printed as well
+            store::Status::Failed if !run.errors.is_empty() => Err(run.errors.remove(0).1.into()),
+            // panics were printed by the panic hook
+            store::Status::Failed if !run.panics.is_empty() => {
+                let (task, message) = run.panics.remove(0);
+                Err(anyhow!("Task {} panicked: {}", task, message))
+            }
+            _ => {
+                let last = self.dag.execution_order().last().unwrap();
+                Ok(run.outputs.remove(last).flatten())
             }
-
-            self.graph
-                .get(task_name)
-                .unwrap_or(&vec![])
-                .iter()
-                .for_each(|child| {
-                    self.tasks
-                        .get_mut(child)
-                        .unwrap()
-                        .set_argument(task_name, &output)
-                })
         }
-
-        Ok(output)
     }
 }
@@ -1,3 +1,14 @@
+#[cfg(feature = "python")]
 mod config_loader;
+#[cfg(feature = "python")]
+pub mod crontab;
+#[cfg(feature = "python")]
 pub mod graph;
+pub mod runner;
+#[cfg(feature = "python")]
 pub mod task;
+pub mod topology;
+pub mod trigger;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,327 @@
+use super::topology::Topology;
+use super::trigger::{graph_status, TaskState, TriggerRule};
+use crate::store::Status;
+use anyhow::{anyhow, Result};
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Tasks and the edges between them, whatever a task is. `Graph` holds Python
+/// tasks, Rust code can run any callable, see `run`
+#[derive(Clone, Debug)]
+pub struct Dag<T> {
+    topology: Topology,
+    tasks: HashMap<String, T>,
+    trigger_rules: HashMap<String, TriggerRule>,
+    // parents before children, sorted before the first run
+    execution_order: Vec<String>,
+    // None runs every ready task at once
+    max_parallel_tasks: Option<usize>,
+    timeout: Option<Duration>,
+}
+
+/// What happened to the tasks of a run, also of one that timed out
+#[derive(Debug)]
+pub struct Run<O, E> {
+    pub states: HashMap<String, TaskState>,
+    pub outputs: HashMap<String, O>,
+    /// Errors of the failed tasks, in the order they failed
+    pub errors: Vec<(String, E)>,
+    /// Panic messages of the tasks that panicked, which failed as well
+    pub panics: Vec<(String, String)>,
+    pub timed_out: bool,
+}
+
+impl<O, E> Run<O, E> {
+    /// Timed out, or failed if any task did, even if a `one_failed` task handled it
+    pub fn status(&self) -> Status {
+        match self.timed_out {
+            true => Status::TimedOut,
+            false => graph_status(self.states.values()),
+        }
+    }
+}
+
+impl<T> Default for Dag<T> {
+    fn default() -> Self {
+        Dag {
+            topology: Topology::new(),
+            tasks: HashMap::new(),
+            trigger_rules: HashMap::new(),
+            execution_order: Vec::new(),
+            max_parallel_tasks: None,
+            timeout: None,
+        }
+    }
+}
+
+impl<T> Dag<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At most this many tasks of a run at a time, None for no limit
+    pub fn with_max_parallel_tasks(mut self, max_parallel_tasks: Option<usize>) -> Result<Self> {
+        if max_parallel_tasks == Some(0) {
+            return Err(anyhow!("max_parallel_tasks must be at least 1"));
+        }
+        self.max_parallel_tasks = max_parallel_tasks;
+        Ok(self)
+    }
+
+    /// Runs taking longer stop waiting for their tasks, see `run`
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adding a task under a name that's taken keeps the first one
+    pub fn add_task(&mut self, name: &str, trigger_rule: TriggerRule, task: T) {
+        self.topology.add_task(name);
+        if !self.tasks.contains_key(name) {
+            self.tasks.insert(name.to_string(), task);
+            self.trigger_rules.insert(name.to_string(), trigger_rule);
+        }
+        self.execution_order.clear();
+    }
+
+    /// Both tasks must be added as well before the graph runs
+    pub fn add_edge(&mut self, parent: &str, child: &str) {
+        self.topology.add_edge(parent, child);
+        self.execution_order.clear();
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Tasks in the order of the last run, empty before the first one
+    pub fn execution_order(&self) -> &[String] {
+        &self.execution_order
+    }
+
+    /// Runs every task on a thread of its own once all its parents are done and its trigger
+    /// rule holds, at most `max_parallel_tasks` at a time. `start` gets a task and the outputs
+    /// of its parents, None for parents that failed or didn't run, and returns the call to
+    /// make on the thread. Tasks that don't run are skipped or upstream_failed, tasks that
+    /// panic fail. A run that times out stops waiting and leaves the running tasks behind
+    pub fn run<O, E, J>(
+        &mut self,
+        mut start: impl FnMut(&str, &T, Vec<(&str, Option<O>)>) -> J,
+    ) -> Result<Run<O, E>>
+    where
+        O: Clone + Send + 'static,
+        E: Send + 'static,
+        J: FnOnce() -> Result<O, E> + Send + 'static,
+    {
+        if self.is_empty() {
+            return Err(anyhow!("Graph is empty"));
+        }
+        if let Some(task) = self.topology.tasks().find(|t| !self.tasks.contains_key(*t)) {
+            return Err(anyhow!("Task {} was never added", task));
+        }
+        if self.execution_order.is_empty() {
+            self.execution_order = self.topology.sort()?;
+        }
+
+        let mut scheduler = Scheduler::new(
+            &self.topology,
+            &self.execution_order,
+            &self.trigger_rules,
+            self.max_parallel_tasks,
+        );
+        let mut outputs: HashMap<&str, O> = HashMap::new();
+        let mut errors = vec![];
+        let mut panics = vec![];
+        let (sender, receiver) = mpsc::channel();
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut timed_out = false;
+        while !scheduler.is_done() {
+            while let Some(name) = scheduler.start() {
+                let inputs = self
+                    .topology
+                    .parents(name)
+                    .iter()
+                    .map(|parent| (parent.as_str(), outputs.get(parent.as_str()).cloned()))
+                    .collect();
+                let job = start(name, &self.tasks[name], inputs);
+                let (name, sender) = (name.to_string(), sender.clone());
+
+                thread::spawn(move || {
+                    let output = panic::catch_unwind(AssertUnwindSafe(job));
+                    // nobody is waiting once the run timed out
+                    let _ = sender.send((name, output));
+                });
+            }
+
+            // every task sends its output, also one that panicked, so waits without a
+            // deadline end once a task does
+            let received = match deadline {
+                Some(deadline) => {
+                    receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => Ok(receiver.recv().unwrap()),
+            };
+            let Ok((name, output)) = received else {
+                timed_out = true;
+                break;
+            };
+            let state = match output {
+                Ok(Ok(output)) => {
+                    outputs.insert(scheduler.task(&name), output);
+                    TaskState::Success
+                }
+                Ok(Err(e)) => {
+                    errors.push((name.clone(), e));
+                    TaskState::Failed
+                }
+                Err(payload) => {
+                    panics.push((name.clone(), panic_message(payload)));
+                    TaskState::Failed
+                }
+            };
+            scheduler.finish(&name, state);
+        }
+
+        Ok(Run {
+            states: owned(scheduler.states),
+            outputs: owned(outputs),
+            errors,
+            panics,
+            timed_out,
+        })
+    }
+}
+
+/// Which tasks of a run start when, given the ones that finished. Only bookkeeping,
+/// `Dag::run` starts the tasks and waits for them
+#[derive(Debug)]
+pub struct Scheduler<'a> {
+    topology: &'a Topology,
+    trigger_rules: &'a HashMap<String, TriggerRule>,
+    limit: usize,
+    // parents each task still waits for
+    waiting: HashMap<&'a str, usize>,
+    ready: VecDeque<&'a str>,
+    running: usize,
+    states: HashMap<&'a str, TaskState>,
+}
+
+impl<'a> Scheduler<'a> {
+    /// Roots are ready in `execution_order`, tasks without a trigger rule use the default
+    pub fn new(
+        topology: &'a Topology,
+        execution_order: &'a [String],
+        trigger_rules: &'a HashMap<String, TriggerRule>,
+        max_parallel_tasks: Option<usize>,
+    ) -> Self {
+        let waiting: HashMap<&str, usize> = topology
+            .tasks()
+            .map(|task| (task, topology.parents(task).len()))
+            .collect();
+        let ready = execution_order
+            .iter()
+            .map(String::as_str)
+            .filter(|task| waiting[task] == 0)
+            .collect();
+
+        Scheduler {
+            topology,
+            trigger_rules,
+            limit: max_parallel_tasks.unwrap_or(usize::MAX),
+            waiting,
+            ready,
+            running: 0,
+            states: HashMap::new(),
+        }
+    }
+
+    /// The next task to run, None while the limit is reached or no task is ready
+    pub fn start(&mut self) -> Option<&'a str> {
+        if self.running >= self.limit {
+            return None;
+        }
+        let task = self.ready.pop_front()?;
+        self.running += 1;
+        Some(task)
+    }
+
+    /// Records the state of a started task, which may make its children ready
+    pub fn finish(&mut self, task: &str, state: TaskState) {
+        self.running -= 1;
+
+        // tasks whose state is known, their children may be ready or not run at all
+        let mut done = vec![(self.task(task), state)];
+        while let Some((task, state)) = done.pop() {
+            self.states.insert(task, state);
+
+            for child in self.topology.children(task) {
+                let parents = self.waiting.get_mut(child.as_str()).unwrap();
+                *parents -= 1;
+                if *parents > 0 {
+                    continue;
+                }
+
+                let parents: Vec<TaskState> = self
+                    .topology
+                    .parents(child)
+                    .iter()
+                    .map(|parent| self.states[parent.as_str()])
+                    .collect();
+                let rule = self.trigger_rules.get(child).copied().unwrap_or_default();
+                match rule.evaluate(&parents) {
+                    None => self.ready.push_back(child),
+                    Some(state) => done.push((child, state)),
+                }
+            }
+        }
+    }
+
+    /// Nothing runs and nothing is left to start
+    pub fn is_done(&self) -> bool {
+        self.running == 0 && self.ready.is_empty()
+    }
+
+    pub fn running(&self) -> usize {
+        self.running
+    }
+
+    /// States of the tasks done so far
+    pub fn states(&self) -> &HashMap<&'a str, TaskState> {
+        &self.states
+    }
+
+    // the name as borrowed from the topology, task threads send owned names
+    fn task(&self, task: &str) -> &'a str {
+        self.waiting.get_key_value(task).unwrap().0
+    }
+}
+
+// What `panic!` was called with, panics with other payloads are rare
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Box<dyn Any>".to_string(),
+        },
+    }
+}
+
+fn owned<V>(map: HashMap<&str, V>) -> HashMap<String, V> {
+    map.into_iter()
+        .map(|(task, value)| (task.to_string(), value))
+        .collect()
+}
@@ -1,9 +1,13 @@
+use super::trigger::{parse_trigger_rule, TriggerRule};
+use anyhow::{anyhow, Result};
 use pyo3::{
+    exceptions::PyTimeoutError,
     prelude::*,
     types::{IntoPyDict, PyCFunction, PyDict, PyTuple},
 };
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::time::Duration;
-use std::{collections::HashMap, thread::sleep};
+use std::{collections::HashMap, thread, thread::sleep};
 
 pub type Message = Option<Py<PyAny>>;
 
@@ -15,23 +19,58 @@ pub struct Task {
     retries: u64,
     retry_delay: f64,
     backoff: f64,
+    trigger_rule: TriggerRule,
+    // of every attempt
+    timeout: Option<Duration>,
+    // `async def` callables, awaited on an event loop of their own
+    is_async: bool,
     callable: PyObject,
 }
 
+fn is_coroutine_function(py: Python<'_>, callable: &PyObject) -> PyResult<bool> {
+    py.import("inspect")?
+        .call_method1("iscoroutinefunction", (callable,))?
+        .extract()
+}
+
+/// Timeouts in seconds, for tasks and graphs
+pub fn parse_timeout(timeout: Option<f64>) -> Result<Option<Duration>> {
+    let Some(secs) = timeout else {
+        return Ok(None);
+    };
+    match Duration::try_from_secs_f64(secs) {
+        Ok(timeout) if !timeout.is_zero() => Ok(Some(timeout)),
+        _ => Err(anyhow!(
+            "Timeout {} is not valid, expected seconds above 0",
+            secs
+        )),
+    }
+}
+
 #[pyfunction]
-pub fn task(
+pub fn task<'py>(
     retries: Option<u64>,
     retry_delay: Option<f64>,
     backoff: Option<f64>,
-    py: Python,
-) -> PyResult<&PyCFunction> {
+    trigger_rule: Option<&str>,
+    timeout: Option<f64>,
+    py: Python<'py>,
+) -> PyResult<&'py PyCFunction> {
+    let timeout = parse_timeout(timeout)?;
+    let trigger_rule = trigger_rule
+        .map(parse_trigger_rule)
+        .transpose()?
+        .unwrap_or_default();
     let f = move |args: &PyTuple, _kwargs: Option<&PyDict>| -> PyResult<Task> {
         let callable: PyObject = args.get_item(0)?.into();
-        return Ok(Task {
+        Ok(Task {
             deps: HashMap::new(),
             retries: retries.unwrap_or_default(),
             retry_delay: retry_delay.unwrap_or_default(),
             backoff: backoff.unwrap_or_default(),
+            trigger_rule,
+            timeout,
+            is_async: is_coroutine_function(args.py(), &callable)?,
             name: callable
                 .to_string()
                 .split_whitespace()
@@ -39,7 +78,7 @@ pub fn task(
                 .unwrap()
                 .to_string(),
             callable,
-        });
+        })
     };
     PyCFunction::new_closure(py, None, None, f)
 }
@@ -49,14 +88,38 @@ impl Task {
     #[pyo3(signature = (*args, **kwargs))]
     fn __call__(&self, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<Message> {
         Python::with_gil(|py| -> PyResult<Message> {
-            self.callable.call(py, args, kwargs)?.extract(py)
+            let output = self.callable.call(py, args, kwargs)?;
+            match self.is_async {
+                // every task runs in its own thread, so async tasks of a graph
+                // still run concurrently, each with its own event loop
+                true => py
+                    .import("asyncio")?
+                    .call_method1("run", (output,))?
+                    .extract(),
+                false => output.extract(py),
+            }
         })
     }
 }
 
 impl Task {
-    pub fn add_dep(&mut self, parent: &str) {
-        self.deps.insert(parent.to_string(), None);
+    /// A task without retries, `callable` is called like a decorated function
+    pub fn new(name: &str, callable: PyObject) -> PyResult<Self> {
+        Ok(Task {
+            name: name.into(),
+            deps: HashMap::new(),
+            retries: 0,
+            retry_delay: 0.0,
+            backoff: 0.0,
+            trigger_rule: TriggerRule::default(),
+            timeout: None,
+            is_async: Python::with_gil(|py| is_coroutine_function(py, &callable))?,
+            callable,
+        })
+    }
+
+    pub fn trigger_rule(&self) -> TriggerRule {
+        self.trigger_rule
     }
 
     pub fn set_argument(&mut self, name: &str, value: &Message) {
@@ -68,7 +131,7 @@ impl Task {
 
         let kwargs: Option<&PyDict> = kwargs.extract(py)?;
 
-        let mut msg = self.__call__(args, kwargs);
+        let mut msg = self.attempt(py, args, kwargs);
 
         if msg.is_ok() {
             return msg;
@@ -77,9 +140,10 @@ impl Task {
         for i in 0..self.retries {
             let secs = self.retry_delay + self.retry_delay * self.backoff * i as f64;
             println!("{} failed, sleeping for {}", self.name, secs);
-            sleep(Duration::from_secs_f64(secs));
+            // tasks running in parallel keep going meanwhile
+            py.allow_threads(|| sleep(Duration::from_secs_f64(secs)));
 
-            msg = self.__call__(args, kwargs);
+            msg = self.attempt(py, args, kwargs);
             if msg.is_ok() {
                 return msg;
             }
@@ -87,4 +151,53 @@ impl Task {
 
         msg
     }
+
+    // A call that fails once it takes longer than the timeout. Async tasks are cancelled,
+    // Python can't stop other threads so sync tasks keep running in the background
+    fn attempt(&self, py: Python, args: &PyTuple, kwargs: Option<&PyDict>) -> PyResult<Message> {
+        let Some(timeout) = self.timeout else {
+            return self.__call__(args, kwargs);
+        };
+        let timed_out = || {
+            let secs = timeout.as_secs_f64();
+            PyTimeoutError::new_err(format!("{} timed out after {}s", self.name, secs))
+        };
+
+        if self.is_async {
+            let asyncio = py.import("asyncio")?;
+            // wait_for raises asyncio.TimeoutError, only an alias of TimeoutError from 3.11
+            let asyncio_timeout = asyncio.getattr("TimeoutError")?;
+            let coroutine = self.callable.call(py, args, kwargs)?;
+            let coroutine = asyncio.call_method1("wait_for", (coroutine, timeout.as_secs_f64()))?;
+            return match asyncio.call_method1("run", (coroutine,)) {
+                Err(e)
+                    if e.is_instance_of::<PyTimeoutError>(py)
+                        || e.is_instance(py, asyncio_timeout) =>
+                {
+                    Err(timed_out())
+                }
+                output => output?.extract(),
+            };
+        }
+
+        let (task, args) = (self.clone(), Py::<PyTuple>::from(args));
+        let kwargs: Option<Py<PyDict>> = kwargs.map(Into::into);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let output = Python::with_gil(|py| {
+                task.__call__(
+                    args.as_ref(py),
+                    kwargs.as_ref().map(|kwargs| kwargs.as_ref(py)),
+                )
+            });
+            // nobody is waiting after a timeout
+            let _ = sender.send(output);
+        });
+
+        match py.allow_threads(move || receiver.recv_timeout(timeout)) {
+            Ok(output) => output,
+            Err(RecvTimeoutError::Timeout) => Err(timed_out()),
+            Err(RecvTimeoutError::Disconnected) => Err(anyhow!("{} panicked", self.name).into()),
+        }
+    }
 }
@@ -0,0 +1,410 @@
+use super::runner::{Dag, Scheduler};
+#[cfg(feature = "python")]
+use super::task::{task, Task};
+use super::topology::Topology;
+use super::trigger::{graph_status, parse_trigger_rule, TaskState, TriggerRule};
+use crate::store::Status;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "python")]
+use {
+    pyo3::{exceptions::PyTimeoutError, prelude::*, types::PyTuple},
+    std::time::Instant,
+};
+
+#[test]
+fn topology_sort() {
+    let mut topology = Topology::new();
+    topology.add_edge("extract", "transform");
+    topology.add_edge("transform", "load");
+    topology.add_edge("extract", "load");
+    topology.add_edge("extract", "load");
+    topology.add_task("report");
+
+    let sorted = topology.sort().unwrap();
+    let position = |task| sorted.iter().position(|t| t == task).unwrap();
+    assert_eq!(sorted.len(), 4);
+    assert!(position("extract") < position("transform"));
+    assert!(position("transform") < position("load"));
+
+    assert_eq!(topology.parents("load"), ["transform", "extract"]);
+    assert_eq!(topology.children("extract"), ["transform", "load"]);
+    assert!(topology.parents("report").is_empty());
+}
+
+#[test]
+fn topology_cycle() {
+    let mut topology = Topology::new();
+    topology.add_edge("a", "b");
+    topology.add_edge("b", "c");
+    topology.add_edge("c", "b");
+
+    assert_eq!(
+        topology.sort().unwrap_err().to_string(),
+        "Graph has a cycle"
+    );
+}
+
+#[test]
+fn trigger_rules() {
+    use TaskState::*;
+    let rule = |rule| parse_trigger_rule(rule).unwrap();
+    let cases = [
+        ("all_success", vec![Success, Success], None),
+        ("all_success", vec![Success, Skipped], Some(Skipped)),
+        ("all_success", vec![Skipped, Failed], Some(UpstreamFailed)),
+        ("all_success", vec![UpstreamFailed], Some(UpstreamFailed)),
+        ("all_done", vec![Failed, Skipped], None),
+        ("one_failed", vec![Success, Failed], None),
+        ("one_failed", vec![UpstreamFailed], None),
+        ("one_failed", vec![Success, Skipped], Some(Skipped)),
+        ("none_failed", vec![Success, Skipped], None),
+        ("none_failed", vec![Success, Failed], Some(UpstreamFailed)),
+    ];
+    for (name, parents, expected) in cases {
+        assert_eq!(
+            rule(name).evaluate(&parents),
+            expected,
+            "{} {:?}",
+            name,
+            parents
+        );
+    }
+
+    assert_eq!(rule("ALL_DONE"), TriggerRule::AllDone);
+    assert!(parse_trigger_rule("sometimes").is_err());
+    assert!(matches!(
+        graph_status(&[Success, Skipped]),
+        Status::Completed
+    ));
+    assert!(matches!(
+        graph_status(&[Success, Failed, Success]),
+        Status::Failed
+    ));
+}
+
+#[test]
+fn dag_runs_rust_callables() {
+    let mut dag = Dag::new();
+    let rules = [
+        ("extract", TriggerRule::AllSuccess),
+        ("double", TriggerRule::AllSuccess),
+        ("fail", TriggerRule::AllSuccess),
+        ("never", TriggerRule::AllSuccess),
+        ("cleanup", TriggerRule::AllDone),
+    ];
+    for (task, rule) in rules {
+        dag.add_task(task, rule, task);
+    }
+    dag.add_edge("extract", "double");
+    dag.add_edge("extract", "fail");
+    dag.add_edge("fail", "never");
+    dag.add_edge("double", "cleanup");
+    dag.add_edge("fail", "cleanup");
+
+    // each task doubles the sum of what its parents returned
+    let mut run = dag
+        .run(|name, task, inputs: Vec<(&str, Option<i32>)>| {
+            assert_eq!(name, *task);
+            let sum: i32 = inputs.iter().filter_map(|(_, output)| *output).sum();
+            let task = *task;
+            move || match task {
+                "extract" => Ok(1),
+                "fail" => Err("failed"),
+                _ => Ok(sum * 2),
+            }
+        })
+        .unwrap();
+
+    assert_eq!(run.outputs.remove("double"), Some(2));
+    assert_eq!(run.outputs.remove("cleanup"), Some(4));
+    assert_eq!(run.states["never"], TaskState::UpstreamFailed);
+    assert_eq!(run.states["cleanup"], TaskState::Success);
+    assert_eq!(run.errors, [("fail".to_string(), "failed")]);
+    assert!(matches!(run.status(), Status::Failed));
+    assert_eq!(dag.execution_order().first().unwrap(), "extract");
+}
+
+#[test]
+fn dag_errors() {
+    let start = |_: &str, _: &(), _: Vec<(&str, Option<()>)>| || Ok::<_, ()>(());
+
+    let mut dag: Dag<()> = Dag::new();
+    assert_eq!(dag.run(start).unwrap_err().to_string(), "Graph is empty");
+
+    dag.add_task("a", TriggerRule::AllSuccess, ());
+    dag.add_edge("a", "b");
+    assert_eq!(
+        dag.run(start).unwrap_err().to_string(),
+        "Task b was never added"
+    );
+
+    dag.add_task("b", TriggerRule::AllSuccess, ());
+    dag.add_edge("b", "a");
+    assert_eq!(dag.run(start).unwrap_err().to_string(), "Graph has a cycle");
+
+    assert!(Dag::<()>::new().with_max_parallel_tasks(Some(0)).is_err());
+}
+
+#[test]
+fn scheduler_limit() {
+    let mut topology = Topology::new();
+    topology.add_edge("a", "e");
+    for task in ["b", "c", "d"] {
+        topology.add_task(task);
+    }
+    let order: Vec<String> = ["a", "b", "c", "d", "e"].map(String::from).into();
+    let rules = HashMap::new();
+    let mut scheduler = Scheduler::new(&topology, &order, &rules, Some(2));
+
+    assert_eq!(scheduler.start(), Some("a"));
+    assert_eq!(scheduler.start(), Some("b"));
+    assert_eq!(scheduler.start(), None);
+    assert_eq!(scheduler.running(), 2);
+
+    // e is ready after a, behind the roots that were ready before
+    scheduler.finish("a", TaskState::Success);
+    assert_eq!(scheduler.start(), Some("c"));
+    assert_eq!(scheduler.start(), None);
+    scheduler.finish("b", TaskState::Success);
+    scheduler.finish("c", TaskState::Success);
+    assert_eq!(scheduler.start(), Some("d"));
+    assert_eq!(scheduler.start(), Some("e"));
+    assert_eq!(scheduler.start(), None);
+
+    assert!(!scheduler.is_done());
+    scheduler.finish("d", TaskState::Success);
+    scheduler.finish("e", TaskState::Success);
+    assert!(scheduler.is_done());
+    assert_eq!(scheduler.states().len(), 5);
+}
+
+#[test]
+fn scheduler_dependents() {
+    let mut topology = Topology::new();
+    topology.add_edge("a", "c");
+    topology.add_edge("b", "c");
+    topology.add_edge("c", "d");
+    topology.add_edge("c", "cleanup");
+    let order = topology.sort().unwrap();
+    let rules = HashMap::from([("cleanup".to_string(), TriggerRule::AllDone)]);
+    let mut scheduler = Scheduler::new(&topology, &order, &rules, None);
+
+    let mut roots = [scheduler.start().unwrap(), scheduler.start().unwrap()];
+    roots.sort();
+    assert_eq!(roots, ["a", "b"]);
+    assert_eq!(scheduler.start(), None);
+
+    // c waits for both its parents
+    scheduler.finish("a", TaskState::Success);
+    assert_eq!(scheduler.start(), None);
+    scheduler.finish("b", TaskState::Success);
+    assert_eq!(scheduler.start(), Some("c"));
+
+    // d never runs, cleanup runs whatever happened
+    scheduler.finish("c", TaskState::Failed);
+    assert_eq!(scheduler.start(), Some("cleanup"));
+    assert_eq!(scheduler.start(), None);
+    assert_eq!(scheduler.states()["d"], TaskState::UpstreamFailed);
+    scheduler.finish("cleanup", TaskState::Success);
+    assert!(scheduler.is_done());
+}
+
+#[test]
+fn dag_parallel_tasks() {
+    let mut dag = Dag::new().with_max_parallel_tasks(Some(2)).unwrap();
+    for task in ["a", "b", "c", "d", "e", "f"] {
+        dag.add_task(task, TriggerRule::AllSuccess, ());
+    }
+    dag.add_edge("a", "e");
+    dag.add_edge("b", "e");
+    dag.add_edge("e", "f");
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let finished = Arc::new(Mutex::new(vec![]));
+    let run = dag
+        .run(|name, _, inputs: Vec<(&str, Option<()>)>| {
+            let (running, most, finished) = (running.clone(), most.clone(), finished.clone());
+            let name = name.to_string();
+            let parents: Vec<String> = inputs
+                .iter()
+                .map(|(parent, _)| parent.to_string())
+                .collect();
+            move || {
+                let done = finished.lock().unwrap().clone();
+                if !parents.iter().all(|parent| done.contains(parent)) {
+                    return Err(format!("{} started before its parents", name));
+                }
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                finished.lock().unwrap().push(name);
+                Ok(())
+            }
+        })
+        .unwrap();
+
+    assert_eq!(run.states.len(), 6);
+    assert!(run.errors.is_empty(), "{:?}", run.errors);
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+    assert_eq!(finished.lock().unwrap().last().unwrap(), "f");
+}
+
+#[test]
+fn dag_panics() {
+    let mut dag = Dag::new();
+    for (task, rule) in [
+        ("panic", TriggerRule::AllSuccess),
+        ("never", TriggerRule::AllSuccess),
+        ("cleanup", TriggerRule::AllDone),
+    ] {
+        dag.add_task(task, rule, task);
+    }
+    dag.add_edge("panic", "never");
+    dag.add_edge("panic", "cleanup");
+
+    // without a timeout, the run must not wait for the panicked task forever
+    let run = dag
+        .run(|_, task, _: Vec<(&str, Option<()>)>| {
+            let task = *task;
+            move || match task {
+                "panic" => panic!("{} at the disco", task),
+                _ => Ok::<_, ()>(()),
+            }
+        })
+        .unwrap();
+
+    assert_eq!(run.states["panic"], TaskState::Failed);
+    assert_eq!(run.states["never"], TaskState::UpstreamFailed);
+    assert_eq!(run.states["cleanup"], TaskState::Success);
+    assert!(run.errors.is_empty());
+    assert_eq!(
+        run.panics,
+        [("panic".to_string(), "panic at the disco".to_string())]
+    );
+    assert!(matches!(run.status(), Status::Failed));
+}
+
+#[test]
+fn dag_timeout() {
+    let mut dag = Dag::new().with_timeout(Some(Duration::from_millis(50)));
+    dag.add_task("fast", TriggerRule::AllSuccess, Duration::ZERO);
+    dag.add_task("slow", TriggerRule::AllSuccess, Duration::from_secs(2));
+    dag.add_task("after", TriggerRule::AllSuccess, Duration::ZERO);
+    dag.add_edge("slow", "after");
+
+    let run = dag
+        .run(|_, sleep, _: Vec<(&str, Option<()>)>| {
+            let sleep = *sleep;
+            move || {
+                thread::sleep(sleep);
+                Ok::<_, ()>(())
+            }
+        })
+        .unwrap();
+
+    assert!(run.timed_out);
+    assert!(matches!(run.status(), Status::TimedOut));
+    assert_eq!(run.states.len(), 1);
+    assert_eq!(run.states["fast"], TaskState::Success);
+}
+
+// A decorated function of `TASKS`
+#[cfg(feature = "python")]
+fn python_task(py: Python<'_>, name: &str, retries: Option<u64>, timeout: Option<f64>) -> Task {
+    const TASKS: &str = "import asyncio, time
+def slow():
+    time.sleep(2)
+async def slow_async():
+    await asyncio.sleep(2)
+async def extract():
+    await asyncio.sleep(0)
+    return 1
+attempts = 0
+async def double(extract):
+    global attempts
+    attempts += 1
+    await asyncio.sleep(0)
+    if attempts == 1:
+        raise ValueError('first attempt')
+    return (extract * 2, attempts)
+";
+    let tasks = PyModule::from_code(py, TASKS, "tasks.py", "tasks").unwrap();
+    let decorator = task(retries, None, None, None, timeout, py).unwrap();
+    let callable = tasks.getattr(name).unwrap();
+    decorator.call1((callable,)).unwrap().extract().unwrap()
+}
+
+#[cfg(feature = "python")]
+#[test]
+fn python_task_timeouts() {
+    Python::with_gil(|py| {
+        for name in ["slow", "slow_async"] {
+            let task = python_task(py, name, None, Some(0.1));
+            let started = Instant::now();
+            let e = task.start(py, PyTuple::empty(py), None).unwrap_err();
+
+            assert!(e.is_instance_of::<PyTimeoutError>(py), "{}", e);
+            assert_eq!(
+                e.value(py).to_string(),
+                format!("{} timed out after 0.1s", name)
+            );
+            assert!(started.elapsed() < Duration::from_secs(1));
+        }
+    });
+}
+
+#[cfg(feature = "python")]
+#[test]
+fn python_graph_timeout() {
+    let mut dag = Dag::new().with_timeout(Some(Duration::from_millis(100)));
+    for name in ["slow", "slow_async"] {
+        let task = Python::with_gil(|py| python_task(py, name, None, None));
+        dag.add_task(name, TriggerRule::AllSuccess, task);
+    }
+
+    // like Graph runs them, on other threads that take the GIL
+    let run = dag
+        .run(|_, task, _| {
+            let task = task.clone();
+            move || Python::with_gil(|py| task.start(py, PyTuple::empty(py), None))
+        })
+        .unwrap();
+
+    assert!(run.timed_out);
+    assert!(matches!(run.status(), Status::TimedOut));
+    assert!(run.states.is_empty());
+}
+
+#[cfg(feature = "python")]
+#[test]
+fn python_async_tasks() {
+    let mut dag = Dag::new();
+    for (name, retries) in [("extract", None), ("double", Some(1))] {
+        let task = Python::with_gil(|py| python_task(py, name, retries, None));
+        dag.add_task(name, TriggerRule::AllSuccess, task);
+    }
+    dag.add_edge("extract", "double");
+
+    // like Graph runs them, with the outputs of their parents as arguments
+    let mut run = dag
+        .run(|_, task, inputs| {
+            let mut task = task.clone();
+            for (parent, output) in inputs {
+                task.set_argument(parent, &output.flatten());
+            }
+            move || Python::with_gil(|py| task.start(py, PyTuple::empty(py), None))
+        })
+        .unwrap();
+
+    assert!(run.errors.is_empty(), "{:?}", run.errors);
+    // the output of the awaited parent, and a fresh coroutine for the retry
+    let double = run.outputs.remove("double").flatten().unwrap();
+    let double: (i64, i64) = Python::with_gil(|py| double.extract(py)).unwrap();
+    assert_eq!(double, (2, 2));
+}
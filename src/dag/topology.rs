@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, VecDeque};
+
+/// Tasks of a graph and the edges between them, by name
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    children: HashMap<String, Vec<String>>,
+    // every task has an entry, roots have no parents
+    parents: HashMap<String, Vec<String>>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_task(&mut self, task: &str) {
+        self.parents.entry(task.into()).or_default();
+    }
+
+    /// Adds both tasks if needed, adding the same edge twice has no effect
+    pub fn add_edge(&mut self, parent: &str, child: &str) {
+        self.add_task(parent);
+        let parents = self.parents.entry(child.into()).or_default();
+        if parents.iter().any(|p| p == parent) {
+            return;
+        }
+        parents.push(parent.into());
+        self.children
+            .entry(parent.into())
+            .or_default()
+            .push(child.into());
+    }
+
+    pub fn tasks(&self) -> impl Iterator<Item = &str> {
+        self.parents.keys().map(String::as_str)
+    }
+
+    pub fn children(&self, task: &str) -> &[String] {
+        self.children.get(task).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn parents(&self, task: &str) -> &[String] {
+        self.parents.get(task).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    /// Tasks in an order where parents come before their children
+    pub fn sort(&self) -> Result<Vec<String>> {
+        let mut sorted = vec![];
+        let mut in_degrees: HashMap<&str, usize> = self
+            .parents
+            .iter()
+            .map(|(task, parents)| (task.as_str(), parents.len()))
+            .collect();
+
+        let mut queue: VecDeque<&str> = in_degrees
+            .iter()
+            .filter(|(_, degree)| **degree == 0)
+            .map(|(task, _)| *task)
+            .collect();
+
+        while let Some(task) = queue.pop_front() {
+            sorted.push(task.to_string());
+
+            for child in self.children(task) {
+                let degree = in_degrees.get_mut(child.as_str()).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push_back(child);
+                }
+            }
+        }
+
+        if sorted.len() < self.parents.len() {
+            return Err(anyhow!("Graph has a cycle"));
+        }
+        Ok(sorted)
+    }
+}
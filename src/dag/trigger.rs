@@ -0,0 +1,82 @@
+use crate::store::Status;
+use anyhow::{anyhow, Result};
+
+/// When a task runs, given the states of its parents. Roots always run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TriggerRule {
+    /// Every parent succeeded
+    #[default]
+    AllSuccess,
+    /// Every parent is done, whatever its state, like a cleanup task
+    AllDone,
+    /// A parent failed, like an alerting task
+    OneFailed,
+    /// No parent failed, skipped parents are fine
+    NoneFailed,
+}
+
+/// State of a task once the run is done with it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskState {
+    Success,
+    Failed,
+    /// Its trigger rule didn't hold, without a failure upstream
+    Skipped,
+    /// Its trigger rule didn't hold because a task upstream failed
+    UpstreamFailed,
+}
+
+pub fn parse_trigger_rule(rule: &str) -> Result<TriggerRule> {
+    match rule.to_lowercase().as_str() {
+        "all_success" => Ok(TriggerRule::AllSuccess),
+        "all_done" => Ok(TriggerRule::AllDone),
+        "one_failed" => Ok(TriggerRule::OneFailed),
+        "none_failed" => Ok(TriggerRule::NoneFailed),
+        _ => Err(anyhow!(
+            "Unknown trigger rule {}, use \"all_success\", \"all_done\", \"one_failed\" or \"none_failed\"",
+            rule
+        )),
+    }
+}
+
+impl TriggerRule {
+    /// None if the task should run, otherwise the state it ends up in
+    pub fn evaluate(&self, parents: &[TaskState]) -> Option<TaskState> {
+        let failed = parents.iter().any(TaskState::is_failed);
+        let succeeded = parents.iter().all(|state| *state == TaskState::Success);
+
+        match self {
+            TriggerRule::AllSuccess if succeeded => None,
+            TriggerRule::AllSuccess | TriggerRule::NoneFailed if failed => {
+                Some(TaskState::UpstreamFailed)
+            }
+            TriggerRule::AllSuccess => Some(TaskState::Skipped),
+            TriggerRule::AllDone | TriggerRule::NoneFailed => None,
+            TriggerRule::OneFailed if failed => None,
+            TriggerRule::OneFailed => Some(TaskState::Skipped),
+        }
+    }
+}
+
+impl TaskState {
+    pub fn is_failed(&self) -> bool {
+        matches!(self, TaskState::Failed | TaskState::UpstreamFailed)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskState::Success => "success",
+            TaskState::Failed => "failed",
+            TaskState::Skipped => "skipped",
+            TaskState::UpstreamFailed => "upstream_failed",
+        }
+    }
+}
+
+/// A run failed if any of its tasks did, even if a `one_failed` task handled it
+pub fn graph_status<'a>(states: impl IntoIterator<Item = &'a TaskState>) -> Status {
+    match states.into_iter().any(TaskState::is_failed) {
+        true => Status::Failed,
+        false => Status::Completed,
+    }
+}
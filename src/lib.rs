@@ -1,18 +1,38 @@
-mod cron;
-mod dag;
-mod store;
+#![allow(non_local_definitions)]
 
-use cron::expression::Expression;
-use dag::{graph::Graph, task::task};
+pub mod cron;
+pub mod dag;
+pub mod store;
+
+#[cfg(feature = "python")]
+use cron::{
+    calendar::Calendar,
+    composite::Composite,
+    error::CronParseError,
+    expression::Expression,
+    iter::FireTimes,
+    timetable::{AfterRun, Interval},
+};
+#[cfg(feature = "python")]
+use dag::{crontab::from_crontab, graph::Graph, task::task};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use std::{include_str, thread};
 
+#[cfg(feature = "python")]
 #[pymodule]
 fn tm(py: Python, module: &PyModule) -> PyResult<()> {
     thread::spawn(|| store::server::start().unwrap());
 
     let cron_submodule = PyModule::new(py, "cron")?;
     cron_submodule.add_class::<Expression>()?;
+    cron_submodule.add_class::<FireTimes>()?;
+    cron_submodule.add_class::<Composite>()?;
+    cron_submodule.add_class::<Calendar>()?;
+    cron_submodule.add_class::<Interval>()?;
+    cron_submodule.add_class::<AfterRun>()?;
+    cron_submodule.add("CronParseError", py.get_type::<CronParseError>())?;
     module.add_submodule(cron_submodule)?;
 
     let exec_impl = include_str!("./dag/executor.py");
@@ -21,6 +41,7 @@ fn tm(py: Python, module: &PyModule) -> PyResult<()> {
     module.add_class::<Graph>()?;
     module.add_submodule(executor)?;
     module.add_function(wrap_pyfunction!(task, module)?)?;
+    module.add_function(wrap_pyfunction!(from_crontab, module)?)?;
 
     // expose the class directly, instead of going through the submodule
     // and allow syntax `from tm.cron import ...`
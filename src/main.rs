@@ -1,11 +1,185 @@
-use anyhow::Result;
-use chrono::Utc;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand};
+use std::process::ExitCode;
+use tm::cron::{error::CronError, expression::Expression};
 
-mod cron;
+/// Taskmaster command-line tools
+#[derive(Parser)]
+#[command(name = "tm")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect cron expressions without Python
+    #[command(subcommand)]
+    Cron(CronCommand),
+}
+
+#[derive(Subcommand)]
+enum CronCommand {
+    /// Print the upcoming fire times
+    Next {
+        #[command(flatten)]
+        expression: ExpressionArgs,
+        /// Number of fire times to print
+        #[arg(short, default_value_t = 10)]
+        n: usize,
+        /// Start from this time instead of now, see `matches` for the format
+        #[arg(long)]
+        from: Option<String>,
+    },
+    /// Check an expression, pointing at the bad token if it's invalid
+    Validate {
+        #[command(flatten)]
+        expression: ExpressionArgs,
+    },
+    /// Describe an expression in English
+    Explain {
+        #[command(flatten)]
+        expression: ExpressionArgs,
+    },
+    /// Check whether the expression fires at a time, exits with 1 if it doesn't.
+    /// Invalid arguments exit with 2 for every command
+    Matches {
+        #[command(flatten)]
+        expression: ExpressionArgs,
+        /// RFC 3339 time like 2024-03-31T09:00:00+02:00, or a wall-clock time
+        /// like "2024-03-31 09:00" in the expression's timezone
+        time: String,
+    },
+}
+
+#[derive(Args)]
+struct ExpressionArgs {
+    /// Cron expression, macro like @daily, `@every 1h` or `at:<datetime>`
+    expression: String,
+    /// IANA timezone the expression is evaluated in, UTC if not set
+    #[arg(long)]
+    tz: Option<String>,
+    /// Combine a restricted day-of-month and day-of-week with `or` or `and`
+    #[arg(long)]
+    day_semantics: Option<String>,
+    /// Name `H` tokens are hashed from, like a graph name
+    #[arg(long, default_value = "")]
+    hash_key: String,
+}
+
+impl ExpressionArgs {
+    fn parse(&self) -> Result<Expression> {
+        let mut expression = Expression::from_str_with_key(&self.expression, &self.hash_key)?;
+        if let Some(tz) = &self.tz {
+            expression = expression.with_timezone(tz)?;
+        }
+        if let Some(day_semantics) = &self.day_semantics {
+            expression = expression.with_day_semantics(day_semantics)?;
+        }
+        Ok(expression)
+    }
+}
+
+impl CronCommand {
+    fn expression(&self) -> &ExpressionArgs {
+        match self {
+            CronCommand::Next { expression, .. }
+            | CronCommand::Validate { expression }
+            | CronCommand::Explain { expression }
+            | CronCommand::Matches { expression, .. } => expression,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let Command::Cron(command) = Cli::parse().command;
+    let expression = command.expression().expression.clone();
+
+    match run(command) {
+        Ok(code) => code,
+        Err(e) => {
+            report(&expression, &e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(command: CronCommand) -> Result<ExitCode> {
+    match command {
+        CronCommand::Next {
+            expression,
+            n,
+            from,
+        } => {
+            let expression = expression.parse()?;
+            let from = match from {
+                Some(from) => parse_time(&expression, &from)?,
+                None => Utc::now(),
+            };
+            for time in expression.iter_from(from).take(n) {
+                println!("{}", format_time(&expression, time));
+            }
+        }
+        CronCommand::Validate { expression } => {
+            println!("valid: {}", expression.parse()?);
+        }
+        CronCommand::Explain { expression } => {
+            println!("{}", expression.parse()?.describe());
+        }
+        CronCommand::Matches { expression, time } => {
+            let expression = expression.parse()?;
+            let matches = expression.matches_utc(parse_time(&expression, &time)?);
+            println!("{}", matches);
+            if !matches {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Points at the bad token of invalid expressions
+fn report(expression: &str, error: &anyhow::Error) {
+    eprintln!("error: {}", error);
+    if let Some(at) = error
+        .downcast_ref::<CronError>()
+        .and_then(CronError::position)
+    {
+        eprintln!("  {}", expression);
+        eprintln!("  {}^ {}", " ".repeat(at.offset), at.field);
+    }
+}
+
+// Naive times are wall-clock times in the expression's timezone, like in Python
+fn parse_time(expression: &Expression, time: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let formats = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ];
+    let naive = formats
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(time, format).ok())
+        .ok_or_else(|| anyhow!("Time {} is not valid, expected 2024-03-31 09:00", time))?;
+
+    let tz = expression.timezone.unwrap_or(Tz::UTC);
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("Time {} doesn't exist in {}", time, tz.name()))
+}
 
-fn main() -> Result<()> {
-    let e = cron::expression::Expression::from_str("* * * * *").unwrap();
-    let now = Utc::now().naive_utc();
-    println!("{}, {}", now, e.next(now));
-    Ok(())
+fn format_time(expression: &Expression, time: DateTime<Utc>) -> String {
+    let tz = expression.timezone.unwrap_or(Tz::UTC);
+    time.with_timezone(&tz)
+        .format("%a %Y-%m-%d %H:%M:%S %Z")
+        .to_string()
 }
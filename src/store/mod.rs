@@ -12,6 +12,8 @@ pub enum Status {
     Completed,
     Running,
     Failed,
+    /// The graph ran longer than its timeout
+    TimedOut,
 }
 
 impl ToSql for Status {
@@ -20,6 +22,7 @@ impl ToSql for Status {
             Self::Failed => "failed",
             Self::Completed => "completed",
             Self::Running => "running",
+            Self::TimedOut => "timed_out",
         }
         .to_sql()
     }